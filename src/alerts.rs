@@ -0,0 +1,480 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+use ureq::Agent;
+
+use crate::meter_reading::MeterReading;
+use crate::storage::{ReadingStore, StoredReading};
+
+/// How often rules on the age of the latest reading are checked while no readings arrive.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Attempts to deliver a notification before giving up on it.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// The `[alerts]` section of the config.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertConfig {
+    /// URL notifications are posted to, unless a rule has its own.
+    pub webhook: Option<String>,
+    pub rules: Vec<AlertRule>,
+}
+
+/// The value a rule watches.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// The sum of all lines in W, see `MeterReading::total_power`.
+    TotalPower,
+    LineOne,
+    LineTwo,
+    LineThree,
+    /// The power of the most loaded line in W.
+    MaxLine,
+    /// The power fed into the grid in W, 0 while drawing from it.
+    FeedIn,
+    /// Seconds since the latest reading.
+    ReadingAge,
+    /// The projected demand of the current window in W, watched by the demand tracker rather than a rule.
+    #[serde(skip_deserializing)]
+    Demand,
+}
+
+impl Metric {
+    fn label(&self) -> &'static str {
+        match self {
+            Metric::TotalPower => "total power",
+            Metric::LineOne => "power of line one",
+            Metric::LineTwo => "power of line two",
+            Metric::LineThree => "power of line three",
+            Metric::MaxLine => "power of the most loaded line",
+            Metric::FeedIn => "feed-in",
+            Metric::ReadingAge => "age of the latest reading",
+            Metric::Demand => "projected demand",
+        }
+    }
+
+    fn value(&self, reading: &MeterReading) -> Option<f64> {
+        let lines = [reading.line_one, reading.line_two, reading.line_three];
+
+        match self {
+            Metric::TotalPower => reading.total_power().map(|power| power as f64),
+            Metric::LineOne => reading.line_one.map(f64::from),
+            Metric::LineTwo => reading.line_two.map(f64::from),
+            Metric::LineThree => reading.line_three.map(f64::from),
+            Metric::MaxLine => lines.into_iter().flatten().max().map(f64::from),
+            Metric::FeedIn => reading.total_power().map(|power| (-power).max(0) as f64),
+            Metric::ReadingAge => Some(0.0),
+            Metric::Demand => None,
+        }
+    }
+}
+
+/// Raises an alert once `metric` has been above `above` (or below `below`) for `duration` seconds.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    pub metric: Metric,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    #[serde(default, rename = "for")]
+    pub duration: i64,
+    /// How far the value has to get back past the threshold for the alert to resolve,
+    /// so a value hovering around the threshold doesn't raise one alert after the other.
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Seconds after a notification in which the rule doesn't notify again.
+    #[serde(default)]
+    pub cooldown: i64,
+    pub webhook: Option<String>,
+}
+
+impl AlertConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        for rule in &self.rules {
+            if rule.above.is_some() == rule.below.is_some() {
+                bail!("The alert rule \"{}\" needs either `above` or `below`.", rule.name);
+            }
+
+            if rule.duration < 0 || rule.cooldown < 0 || rule.hysteresis < 0.0 {
+                bail!("`for`, `cooldown` and `hysteresis` of the alert rule \"{}\" can't be negative.", rule.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Triggered,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Triggered => "triggered",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// A notification about a rule, as posted to the webhook and logged to the `Alerts` table.
+#[derive(Clone, Serialize)]
+pub struct Alert {
+    pub timestamp: i64,
+    pub meter: String,
+    pub rule: String,
+    pub state: AlertState,
+    pub metric: Metric,
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+}
+
+struct RuleState {
+    rule: AlertRule,
+    /// Since when the threshold has been exceeded, while waiting for `duration` to pass.
+    pending_since: Option<i64>,
+    active: bool,
+    /// Whether the current alert was notified, only then its resolution is.
+    notified: bool,
+    last_notified: Option<i64>,
+}
+
+impl RuleState {
+    fn evaluate(&mut self, value: f64, now: i64) -> Option<AlertState> {
+        let (exceeded, recovered) = match (self.rule.above, self.rule.below) {
+            (Some(above), _) => (value > above, value <= above - self.rule.hysteresis),
+            (_, Some(below)) => (value < below, value >= below + self.rule.hysteresis),
+            _ => return None,
+        };
+
+        if self.active {
+            if !recovered {
+                return None;
+            }
+
+            self.active = false;
+            self.pending_since = None;
+            return std::mem::take(&mut self.notified).then_some(AlertState::Resolved);
+        }
+
+        if !exceeded {
+            self.pending_since = None;
+            return None;
+        }
+
+        let since = *self.pending_since.get_or_insert(now);
+        if now - since < self.rule.duration {
+            return None;
+        }
+
+        self.active = true;
+        if self.last_notified.is_some_and(|last| now - last < self.rule.cooldown) {
+            return None;
+        }
+
+        self.notified = true;
+        self.last_notified = Some(now);
+        Some(AlertState::Triggered)
+    }
+
+    fn alert(&self, meter: &str, state: AlertState, value: f64, now: i64) -> Alert {
+        let rule = &self.rule;
+        let (threshold, direction) = match rule.above {
+            Some(above) => (above, "above"),
+            None => (rule.below.unwrap_or_default(), "below"),
+        };
+
+        let message = match state {
+            AlertState::Triggered => format!("{}: The {} of {meter} is {value:.0}, {direction} {threshold:.0}.", rule.name, rule.metric.label()),
+            AlertState::Resolved => format!("{}: The {} of {meter} is back to {value:.0}.", rule.name, rule.metric.label()),
+        };
+
+        Alert {
+            timestamp: now,
+            meter: meter.to_string(),
+            rule: rule.name.clone(),
+            state,
+            metric: rule.metric,
+            value,
+            threshold,
+            message,
+        }
+    }
+}
+
+/// Hands alerts to the thread which delivers and logs them.
+#[derive(Clone)]
+pub struct Notifier {
+    alerts: Sender<(Alert, Option<String>)>,
+    default_webhook: Option<String>,
+}
+
+impl Notifier {
+    /// Starts delivering alerts to the webhooks of `config` and logging them to `store`.
+    pub fn start(config: &AlertConfig, store: Box<dyn ReadingStore + Send>) -> Self {
+        let (alerts, receiver) = mpsc::channel();
+        thread::spawn(move || notify(receiver, store.as_ref()));
+
+        Notifier { alerts, default_webhook: config.webhook.clone() }
+    }
+
+    /// Prints the alert and queues it for `webhook`, or the default webhook if there is none.
+    pub fn send(&self, alert: Alert, webhook: Option<&str>) {
+        println!("Alert {}: {}", alert.state.as_str(), alert.message);
+
+        let webhook = webhook.map(str::to_string).or_else(|| self.default_webhook.clone());
+        let _ = self.alerts.send((alert, webhook));
+    }
+}
+
+/// Evaluates the alert rules on every reading and hands the alerts to the notifier.
+pub struct AlertEngine {
+    meter: String,
+    rules: Vec<RuleState>,
+    notifier: Notifier,
+}
+
+impl AlertEngine {
+    pub fn new(meter: &str, config: &AlertConfig, notifier: Notifier) -> Self {
+        let rules = config.rules.iter()
+            .map(|rule| RuleState { rule: rule.clone(), pending_since: None, active: false, notified: false, last_notified: None })
+            .collect();
+
+        AlertEngine {
+            meter: meter.to_string(),
+            rules,
+            notifier,
+        }
+    }
+
+    /// Processes readings until the sender is dropped.
+    pub fn enter(mut self, readings: Receiver<StoredReading>) {
+        // until the first reading arrives, its age counts from the start.
+        let mut latest = now();
+
+        loop {
+            let (now, reading) = match readings.recv_timeout(TICK) {
+                Ok(reading) => (reading.timestamp, Some(reading.reading)),
+                Err(RecvTimeoutError::Timeout) => (now(), None),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            if reading.is_some() {
+                latest = now;
+            }
+
+            for rule in &mut self.rules {
+                let value = match (rule.rule.metric, &reading) {
+                    (Metric::ReadingAge, _) => Some((now - latest) as f64),
+                    (metric, Some(reading)) => metric.value(reading),
+                    (_, None) => None,
+                };
+
+                let Some(value) = value else {
+                    continue;
+                };
+
+                if let Some(state) = rule.evaluate(value, now) {
+                    let alert = rule.alert(&self.meter, state, value, now);
+                    self.notifier.send(alert, rule.rule.webhook.as_deref());
+                }
+            }
+        }
+    }
+}
+
+/// Delivers the alerts to their webhooks and logs them, one after the other so a slow webhook
+/// doesn't hold up the evaluation of the rules.
+fn notify(alerts: Receiver<(Alert, Option<String>)>, store: &dyn ReadingStore) {
+    let agent: Agent = Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .build()
+        .into();
+
+    for (alert, webhook) in alerts {
+        let delivered = webhook.map(|webhook| {
+            for attempt in 1..=MAX_ATTEMPTS {
+                match agent.post(&webhook).send_json(&alert) {
+                    Ok(_) => return true,
+                    Err(error) => {
+                        println!("Warning: Could not deliver alert to {webhook} (attempt {attempt}/{MAX_ATTEMPTS}): {error}");
+                        if attempt < MAX_ATTEMPTS {
+                            thread::sleep(Duration::from_secs(1 << attempt));
+                        }
+                    }
+                }
+            }
+
+            false
+        });
+
+        if let Err(error) = store.insert_alert(&alert, delivered) {
+            println!("Warning: Could not log alert: {error}");
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::storage::{Aggregate, MemoryStore, ReadingQuery};
+    use crate::test_server;
+    use crate::timestamp::TimeRange;
+
+    use super::*;
+
+    /// Keeps the readings in memory and the logged alerts in a list.
+    #[derive(Default)]
+    struct AlertLog {
+        readings: MemoryStore,
+        alerts: Mutex<Vec<(String, Option<bool>)>>,
+    }
+
+    impl ReadingStore for AlertLog {
+        fn insert(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, Error> {
+            self.readings.insert(meter, timestamp, reading)
+        }
+
+        fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error> {
+            self.readings.readings(query)
+        }
+
+        fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+            self.readings.aggregates(range, meter, bucket_secs)
+        }
+
+        fn insert_alert(&self, alert: &Alert, delivered: Option<bool>) -> Result<(), Error> {
+            self.alerts.lock().unwrap().push((alert.rule.clone(), delivered));
+            Ok(())
+        }
+    }
+
+    fn rule(name: &str) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            metric: Metric::TotalPower,
+            above: Some(8000.0),
+            below: None,
+            duration: 0,
+            hysteresis: 0.0,
+            cooldown: 0,
+            webhook: None,
+        }
+    }
+
+    #[test]
+    fn posts_alerts_to_the_webhook_and_logs_them() {
+        let (url, requests) = test_server::serve(200);
+        let state = RuleState { rule: rule("High load"), pending_since: None, active: false, notified: false, last_notified: None };
+        let alert = state.alert("default", AlertState::Triggered, 8230.0, 1711000000);
+        let unhooked = RuleState { rule: rule("Unhooked"), ..state }.alert("default", AlertState::Triggered, 8230.0, 1711000000);
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send((alert, Some(format!("{url}/hook")))).unwrap();
+        sender.send((unhooked, None)).unwrap();
+        drop(sender);
+
+        let log = AlertLog::default();
+        notify(receiver, &log);
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.request_line, "POST /hook HTTP/1.1");
+        assert_eq!(request.header("content-type"), Some("application/json; charset=utf-8"));
+
+        let body = serde_json::from_str::<serde_json::Value>(&request.body).unwrap();
+        assert_eq!(body, serde_json::json!({
+            "timestamp": 1711000000,
+            "meter": "default",
+            "rule": "High load",
+            "state": "triggered",
+            "metric": "total_power",
+            "value": 8230.0,
+            "threshold": 8000.0,
+            "message": "High load: The total power of default is 8230, above 8000.",
+        }));
+
+        assert!(requests.try_recv().is_err());
+        assert_eq!(*log.alerts.lock().unwrap(), [("High load".to_string(), Some(true)), ("Unhooked".to_string(), None)]);
+    }
+
+    /// Feeds `(now, value)` to a rule and checks the state notified for each.
+    fn evaluate(rule: AlertRule, steps: &[(i64, f64, Option<&str>)]) {
+        let mut state = RuleState { rule, pending_since: None, active: false, notified: false, last_notified: None };
+
+        for (index, &(now, value, expected)) in steps.iter().enumerate() {
+            assert_eq!(state.evaluate(value, now).map(|state| state.as_str()), expected, "step {index} at {now}");
+        }
+    }
+
+    #[test]
+    fn waits_for_the_duration_before_triggering() {
+        let rule = AlertRule { above: Some(1000.0), duration: 60, ..rule("High load") };
+
+        evaluate(rule.clone(), &[
+            (0, 1500.0, None),
+            (59, 1500.0, None),
+            (60, 1500.0, Some("triggered")),
+            (61, 1500.0, None),
+            (70, 900.0, Some("resolved")),
+        ]);
+
+        // dropping below the threshold starts the wait over.
+        evaluate(rule, &[
+            (0, 1500.0, None),
+            (30, 900.0, None),
+            (40, 1500.0, None),
+            (99, 1500.0, None),
+            (100, 1500.0, Some("triggered")),
+        ]);
+    }
+
+    #[test]
+    fn resolves_past_the_hysteresis() {
+        let high = AlertRule { above: Some(1000.0), hysteresis: 100.0, ..rule("High load") };
+        evaluate(high, &[
+            (0, 1500.0, Some("triggered")),
+            (1, 950.0, None),
+            (2, 1100.0, None),
+            (3, 900.0, Some("resolved")),
+            (4, 1001.0, Some("triggered")),
+        ]);
+
+        let low = AlertRule { above: None, below: Some(100.0), hysteresis: 50.0, ..rule("Low load") };
+        evaluate(low, &[
+            (0, 100.0, None),
+            (1, 50.0, Some("triggered")),
+            (2, 120.0, None),
+            (3, 150.0, Some("resolved")),
+        ]);
+    }
+
+    #[test]
+    fn resolves_only_notified_alerts() {
+        let rule = AlertRule { above: Some(1000.0), cooldown: 300, ..rule("High load") };
+
+        evaluate(rule, &[
+            (0, 1500.0, Some("triggered")),
+            (10, 900.0, Some("resolved")),
+            // within the cooldown, neither the trigger nor its resolution are notified.
+            (20, 1500.0, None),
+            (30, 900.0, None),
+            (300, 1500.0, Some("triggered")),
+            (310, 900.0, Some("resolved")),
+        ]);
+    }
+}
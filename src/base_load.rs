@@ -0,0 +1,173 @@
+use anyhow::{anyhow, bail, Error};
+use chrono::{DateTime, Local, NaiveTime};
+use serde::Serialize;
+
+use crate::stats::Distribution;
+use crate::storage::{ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::{Interval, TimeRange};
+
+/// How many periods before the latest one make up the baseline it is compared to.
+const BASELINE_PERIODS: usize = 4;
+
+/// What counts as base load: the `percentile` of the total power at night,
+/// when hardly anything but always-on devices is running.
+#[derive(Clone, Copy)]
+pub struct BaseLoadOptions {
+    pub night_from: NaiveTime,
+    pub night_to: NaiveTime,
+    /// Between 0 and 100.
+    pub percentile: f64,
+}
+
+impl Default for BaseLoadOptions {
+    fn default() -> Self {
+        BaseLoadOptions {
+            night_from: NaiveTime::from_hms_opt(1, 0, 0).unwrap(),
+            night_to: NaiveTime::from_hms_opt(5, 0, 0).unwrap(),
+            percentile: 10.0,
+        }
+    }
+}
+
+/// The base load of one period in W, `None` if there were no readings at night.
+#[derive(Serialize)]
+pub struct BaseLoad {
+    pub from: i64,
+    pub to: i64,
+    pub base_load: Option<f64>,
+    pub samples: u64,
+}
+
+#[derive(Serialize)]
+pub struct BaseLoadTrend {
+    /// Change of the base load per period in W, fitted over all periods.
+    pub slope: f64,
+    /// The median base load of the periods before the latest.
+    pub baseline: f64,
+    pub latest: f64,
+    /// `latest - baseline`, a new always-on device shows up here.
+    pub change: f64,
+}
+
+#[derive(Serialize)]
+pub struct BaseLoadReport {
+    pub meter: String,
+    pub period: Interval,
+    pub percentile: f64,
+    pub periods: Vec<BaseLoad>,
+    pub trend: Option<BaseLoadTrend>,
+}
+
+/// Determines the base load per day or week from the total power of the readings at night.
+pub fn base_load(
+    store: &dyn ReadingStore,
+    range: &TimeRange,
+    meter: &str,
+    period: Interval,
+    options: BaseLoadOptions,
+) -> Result<BaseLoadReport, Error> {
+    if let Interval::Hour = period {
+        bail!("The base load needs periods of at least a day.");
+    }
+
+    if !(0.0..=100.0).contains(&options.percentile) {
+        bail!("The percentile has to be between 0 and 100.");
+    }
+
+    let query = ReadingQuery {
+        meter: Some(meter.to_string()),
+        ..ReadingQuery::new(*range)
+    };
+
+    let mut periods = Vec::new();
+    let mut current: Option<(i64, i64, Distribution)> = None;
+
+    for reading in store.readings(&query)? {
+        let StoredReading { timestamp, reading, .. } = reading?;
+        let Some(power) = reading.total_power() else {
+            continue;
+        };
+
+        if current.as_ref().is_some_and(|(_, end, _)| timestamp >= *end) {
+            let (start, end, samples) = current.take().unwrap();
+            periods.push(summarize(start.max(range.from), end.min(range.to), samples, options.percentile));
+        }
+
+        let (_, _, samples) = match &mut current {
+            Some(current) => current,
+            None => {
+                let start = period.start_of(timestamp)?;
+                current.insert((start, period.next(start)?, Distribution::default()))
+            }
+        };
+
+        if is_night(timestamp, &options)? {
+            samples.add(power);
+        }
+    }
+
+    if let Some((start, end, samples)) = current {
+        periods.push(summarize(start.max(range.from), end.min(range.to), samples, options.percentile));
+    }
+
+    Ok(BaseLoadReport {
+        meter: meter.to_string(),
+        period,
+        percentile: options.percentile,
+        trend: trend(&periods),
+        periods,
+    })
+}
+
+fn is_night(timestamp: i64, options: &BaseLoadOptions) -> Result<bool, Error> {
+    let time = DateTime::from_timestamp(timestamp, 0)
+        .ok_or_else(|| anyhow!("Invalid timestamp {timestamp}."))?
+        .with_timezone(&Local)
+        .time();
+
+    Ok(if options.night_from <= options.night_to {
+        options.night_from <= time && time < options.night_to
+    } else {
+        options.night_from <= time || time < options.night_to
+    })
+}
+
+fn summarize(from: i64, to: i64, samples: Distribution, p: f64) -> BaseLoad {
+    BaseLoad {
+        from,
+        to,
+        base_load: (samples.count() > 0).then(|| samples.percentile(p / 100.0)),
+        samples: samples.count(),
+    }
+}
+
+/// Fits a line through the base loads and compares the latest one to those before it.
+fn trend(periods: &[BaseLoad]) -> Option<BaseLoadTrend> {
+    let values = periods.iter()
+        .enumerate()
+        .filter_map(|(index, period)| Some((index as f64, period.base_load?)))
+        .collect::<Vec<_>>();
+
+    let (&(_, latest), previous) = values.split_last()?;
+    if previous.is_empty() {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean_x = values.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = values.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance = values.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>();
+    let variance = values.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+
+    let mut baseline = previous.iter().rev().take(BASELINE_PERIODS).map(|(_, y)| *y).collect::<Vec<_>>();
+    baseline.sort_by(f64::total_cmp);
+    let middle = baseline.len() / 2;
+    let baseline = if baseline.len() % 2 == 0 { (baseline[middle - 1] + baseline[middle]) / 2.0 } else { baseline[middle] };
+
+    Some(BaseLoadTrend {
+        slope: covariance / variance,
+        baseline,
+        latest,
+        change: latest - baseline,
+    })
+}
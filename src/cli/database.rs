@@ -1,17 +1,123 @@
-use anyhow::Error;
-use clap_derive::{Args};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+use clap_derive::{Args, Subcommand};
+
 use crate::database::Database;
+use crate::timestamp::format_timestamp;
+
+/// How many of the longest gaps of each meter are listed by `database check`.
+const LISTED_GAPS: usize = 10;
 
 #[derive(Clone, Args)]
-pub struct DatabaseCommand { }
+pub struct DatabaseCommand {
+    #[command(subcommand)]
+    command: Option<DatabaseCommands>,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum DatabaseCommands {
+    /// Run an integrity check and summarize gaps in the recorded readings.
+    Check {
+        /// Minimum number of seconds without readings to count as a gap.
+        #[arg(long, default_value = "60")]
+        gap: i64,
+    },
+    /// Return unused space to the file system, while readings keep being recorded.
+    Vacuum {
+        /// Rebuild the whole file, which also compacts partly used pages but blocks recording until it is done.
+        #[arg(long, default_value = "false")]
+        full: bool,
+    },
+    /// Copy the database to a new file, while readings keep being recorded.
+    Backup {
+        file: PathBuf,
+    },
+    /// Replace all readings with those of a backup.
+    Restore {
+        file: PathBuf,
+
+        /// Confirm that the current readings get replaced.
+        #[arg(long, default_value = "false")]
+        yes: bool,
+    },
+}
 
 impl DatabaseCommand {
     pub fn run(self) -> Result<(), Error> {
         let db = Database::load()?;
-        let metrics = db.metrics()?;
-        
-        println!("{metrics}");
+
+        match self.command {
+            None => {
+                let metrics = db.metrics()?;
+
+                println!("{metrics}");
+            }
+            Some(DatabaseCommands::Check { gap }) => check(&db, gap)?,
+            Some(DatabaseCommands::Vacuum { full }) => {
+                let size_before = db.metrics()?.file_size;
+                db.vacuum(full)?;
+                let size_after = db.metrics()?.file_size;
+
+                println!("Vacuumed database: {size_before} bytes -> {size_after} bytes");
+            }
+            Some(DatabaseCommands::Backup { file }) => {
+                db.backup(&file)?;
+
+                println!("Backed up database to {} ({} bytes).", file.display(), fs::metadata(&file)?.len());
+            }
+            Some(DatabaseCommands::Restore { file, yes }) => {
+                if !yes {
+                    bail!("Restoring replaces all current readings. Pass --yes to confirm.");
+                }
+
+                db.restore(&file)?;
+
+                println!("Restored database from {}.", file.display());
+                println!("{}", db.metrics()?);
+            }
+        }
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn check(db: &Database, min_gap: i64) -> Result<(), Error> {
+    let problems = db.integrity_check()?;
+    if problems == ["ok"] {
+        println!("Integrity: ok");
+    } else {
+        println!("Integrity: {} problems", problems.len());
+        for problem in &problems {
+            println!("  {problem}");
+        }
+    }
+
+    let spans = db.time_spans()?;
+    if spans.is_empty() {
+        println!("No readings recorded.");
+        return Ok(());
+    }
+
+    let gaps = db.gaps(min_gap)?;
+    for span in spans {
+        let mut gaps = gaps.iter().filter(|gap| gap.meter == span.meter).collect::<Vec<_>>();
+        let missing = gaps.iter().map(|gap| gap.duration()).sum::<i64>();
+        let duration = (span.last - span.first).max(1);
+
+        println!("Meter {}: readings {} to {}", span.meter, format_timestamp(span.first), format_timestamp(span.last));
+        println!(
+            "  Gaps: {} longer than {min_gap}s, {missing}s without readings in total ({:.2}% coverage)",
+            gaps.len(),
+            100.0 * (duration - missing) as f64 / duration as f64
+        );
+
+        gaps.sort_by_key(|gap| -gap.duration());
+        for gap in gaps.iter().take(LISTED_GAPS) {
+            println!("    {} to {} ({}s)", format_timestamp(gap.from), format_timestamp(gap.to), gap.duration());
+        }
+    }
+
+    Ok(())
+}
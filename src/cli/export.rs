@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::Error;
+use clap_derive::Args;
+
+use crate::export::{export, ExportFormat, Rollup};
+use crate::storage::StorageArgs;
+use crate::timestamp::{parse_timestamp, TimeRange};
+
+#[derive(Clone, Args)]
+pub struct ExportCommand {
+    /// Start of the time range (inclusive), as unix seconds, RFC 3339 or YYYY-MM-DD.
+    #[arg(long, value_parser = parse_timestamp)]
+    from: Option<i64>,
+
+    /// End of the time range (exclusive), as unix seconds, RFC 3339 or YYYY-MM-DD.
+    #[arg(long, value_parser = parse_timestamp)]
+    to: Option<i64>,
+
+    /// Only export readings of this meter.
+    #[arg(long)]
+    meter: Option<String>,
+
+    #[arg(long, value_enum, default_value = "csv")]
+    format: ExportFormat,
+
+    /// Export aggregated buckets instead of every reading.
+    #[arg(long, value_enum)]
+    rollup: Option<Rollup>,
+
+    /// File to write the export to. Defaults to stdout.
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    storage: StorageArgs,
+}
+
+impl ExportCommand {
+    pub fn run(self) -> Result<(), Error> {
+        let store = self.storage.open_reader()?;
+        let range = TimeRange::new(self.from, self.to)?;
+
+        let writer: Box<dyn Write + Send> = match &self.output {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+
+        let count = export(store.as_ref(), &range, self.meter.as_deref(), self.rollup, self.format, BufWriter::new(writer))?;
+
+        // stdout may carry the export itself, so report on stderr.
+        eprintln!("Exported {count} rows.");
+
+        Ok(())
+    }
+}
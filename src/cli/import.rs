@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::Error;
+use clap_derive::Args;
+
+use crate::database::Database;
+use crate::import::{import, read_records, ImportFormat, ImportOptions};
+
+#[derive(Clone, Args)]
+pub struct ImportCommand {
+    #[arg(long, value_enum)]
+    format: ImportFormat,
+
+    #[arg(long)]
+    file: PathBuf,
+
+    /// Name of the meter the readings are stored for.
+    #[arg(long, default_value = "default")]
+    meter: String,
+
+    /// Meter reading in Wh at the start of the file, for formats which only contain consumption.
+    #[arg(long, default_value = "0")]
+    initial_reading: f64,
+}
+
+impl ImportCommand {
+    pub fn run(self) -> Result<(), Error> {
+        let database = Database::load()?;
+
+        let options = ImportOptions { initial_reading: self.initial_reading };
+        let records = read_records(self.format, File::open(&self.file)?, &options)?;
+        let summary = import(&database, &self.meter, records)?;
+
+        println!("Imported {}: {summary}.", self.file.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::Error;
+use clap_derive::Args;
+
+use crate::database::Database;
+use crate::import::{read_prices, PriceFormat, PriceUnit};
+use crate::timestamp::format_timestamp;
+
+#[derive(Clone, Args)]
+pub struct ImportPricesCommand {
+    #[arg(long, value_enum)]
+    format: PriceFormat,
+
+    #[arg(long)]
+    file: PathBuf,
+
+    /// Whether the prices of the file are per kWh or per MWh. aWATTar files state their unit themselves.
+    #[arg(long, value_enum, default_value = "kwh")]
+    unit: PriceUnit,
+
+    /// Seconds a price is valid for, if the file only contains its start.
+    #[arg(long, default_value = "3600")]
+    duration: i64,
+}
+
+impl ImportPricesCommand {
+    pub fn run(self) -> Result<(), Error> {
+        let database = Database::load()?;
+
+        let prices = read_prices(self.format, File::open(&self.file)?, self.unit, self.duration)?;
+        let count = database.insert_prices(&prices)?;
+
+        match (prices.first(), prices.last()) {
+            (Some(first), Some(last)) => println!(
+                "Imported {count} prices from {} to {}.",
+                format_timestamp(first.timestamp),
+                format_timestamp(last.timestamp + last.duration),
+            ),
+            _ => println!("No prices found in {}.", self.file.display()),
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
 pub mod root_command;
 mod database;
+mod export;
+mod import;
+mod import_prices;
 mod ports;
+mod report;
 mod start;
\ No newline at end of file
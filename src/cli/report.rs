@@ -0,0 +1,185 @@
+use anyhow::Error;
+use chrono::NaiveTime;
+use clap_derive::{Args, Subcommand};
+
+use crate::base_load::{base_load, BaseLoadOptions};
+use crate::config::ConfigArgs;
+use crate::cost::{cost, Cost, CostPeriod};
+use crate::database::ReadonlyDatabase;
+use crate::imbalance::{imbalance, PhaseShare};
+use crate::prices::SpotPrices;
+use crate::storage::StorageArgs;
+use crate::tariff::Tariff;
+use crate::timestamp::{format_local, parse_timestamp, Interval, TimeRange};
+
+#[derive(Clone, Args)]
+pub struct ReportCommand {
+    #[command(subcommand)]
+    command: ReportCommands,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum ReportCommands {
+    /// Energy cost per day, month or billing period, priced with the tariff of the config or the imported spot prices.
+    Cost {
+        #[command(flatten)]
+        selection: Selection,
+
+        #[arg(long, value_enum, default_value = "month")]
+        period: CostPeriod,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Always-on consumption per day or week, taken from the total power at night.
+    BaseLoad {
+        #[command(flatten)]
+        selection: Selection,
+
+        #[arg(long, value_enum, default_value = "week")]
+        period: Interval,
+
+        /// Local time the night starts.
+        #[arg(long, default_value = "01:00")]
+        night_from: NaiveTime,
+
+        /// Local time the night ends.
+        #[arg(long, default_value = "05:00")]
+        night_to: NaiveTime,
+
+        /// Percentile of the night-time power which counts as base load.
+        #[arg(long, default_value = "10")]
+        percentile: f64,
+    },
+    /// How evenly the power is spread over the three lines, per day, week or month.
+    Imbalance {
+        #[command(flatten)]
+        selection: Selection,
+
+        #[arg(long, value_enum, default_value = "day")]
+        period: Interval,
+
+        /// Difference in W between the most and the least loaded line above which the time is counted.
+        #[arg(long, default_value = "4600")]
+        threshold: f64,
+    },
+}
+
+/// The readings a report is made of.
+#[derive(Clone, Args)]
+pub struct Selection {
+    /// Start of the time range (inclusive), as unix seconds, RFC 3339 or YYYY-MM-DD.
+    #[arg(long, value_parser = parse_timestamp)]
+    from: Option<i64>,
+
+    /// End of the time range (exclusive), as unix seconds, RFC 3339 or YYYY-MM-DD.
+    #[arg(long, value_parser = parse_timestamp)]
+    to: Option<i64>,
+
+    #[arg(long, default_value = "default")]
+    meter: String,
+
+    #[command(flatten)]
+    storage: StorageArgs,
+}
+
+impl ReportCommand {
+    pub fn run(self) -> Result<(), Error> {
+        match self.command {
+            ReportCommands::Cost { selection, period, config } => {
+                // without a tariff, the spot prices are all there is.
+                let tariff = config.load()?.tariff.unwrap_or_else(Tariff::spot_only);
+
+                let range = TimeRange::new(selection.from, selection.to)?;
+                let prices = match ReadonlyDatabase::load() {
+                    Ok(database) => SpotPrices::new(database.prices(&range)?),
+                    Err(_) => SpotPrices::default(),
+                };
+
+                let store = selection.storage.open_reader()?;
+                let report = cost(store.as_ref(), &tariff, &prices, &range, &selection.meter, period)?;
+
+                let format = if let CostPeriod::Month = period { "%Y-%m" } else { "%Y-%m-%d" };
+
+                println!(
+                    "{:<12} {:>12} {:>12} {:>12} {:>12} {:>10} {:>12}",
+                    "Period", "Energy kWh", "Energy", "Feed-in kWh", "Compensation", "Fixed", "Total",
+                );
+
+                for cost in &report.periods {
+                    print_cost(&format_local(cost.from, format), cost);
+                }
+
+                print_cost("Total", &report.total);
+                println!("All amounts in {}.", report.currency);
+            }
+            ReportCommands::BaseLoad { selection, period, night_from, night_to, percentile } => {
+                let range = TimeRange::new(selection.from, selection.to)?;
+                let store = selection.storage.open_reader()?;
+                let options = BaseLoadOptions { night_from, night_to, percentile };
+                let report = base_load(store.as_ref(), &range, &selection.meter, period, options)?;
+
+                let format = if let Interval::Month = period { "%Y-%m" } else { "%Y-%m-%d" };
+                println!("{:<12} {:>12} {:>10}", "Period", "Base load W", "Samples");
+
+                for period in &report.periods {
+                    let base_load = period.base_load.map_or("-".to_string(), |base_load| format!("{base_load:.1}"));
+                    println!("{:<12} {:>12} {:>10}", format_local(period.from, format), base_load, period.samples);
+                }
+
+                if let Some(trend) = report.trend {
+                    println!();
+                    println!("Trend: {:+.1} W per period", trend.slope);
+                    println!("Latest: {:.1} W, {:+.1} W compared to the median of the periods before ({:.1} W)", trend.latest, trend.change, trend.baseline);
+                }
+            }
+            ReportCommands::Imbalance { selection, period, threshold } => {
+                let range = TimeRange::new(selection.from, selection.to)?;
+                let store = selection.storage.open_reader()?;
+                let report = imbalance(store.as_ref(), &range, &selection.meter, Some(period), threshold)?;
+
+                let format = match period {
+                    Interval::Hour => "%Y-%m-%d %H:00",
+                    Interval::Month => "%Y-%m",
+                    _ => "%Y-%m-%d",
+                };
+
+                println!(
+                    "{:<16} {:>8} {:>8} {:>8} {:>14} {:>15} {:>16}",
+                    "Period", "L1 %", "L2 %", "L3 %", "Max dev. W", "Max asym. W", "Above threshold",
+                );
+
+                for bucket in &report.buckets {
+                    let optional = |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{value:.0}"));
+                    let above = if bucket.covered > 0 { bucket.above_threshold as f64 / bucket.covered as f64 * 100.0 } else { 0.0 };
+
+                    println!(
+                        "{:<16} {:>8} {:>8} {:>8} {:>14} {:>15} {:>15.1}%",
+                        format_local(bucket.from, format),
+                        share(&bucket.line_one),
+                        share(&bucket.line_two),
+                        share(&bucket.line_three),
+                        optional(bucket.max_deviation),
+                        optional(bucket.max_asymmetry.map(|value| value as f64)),
+                        above,
+                    );
+                }
+
+                println!("Shares of the energy drawn per line, time above an asymmetry of {threshold:.0} W as part of the time covered by readings.");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn print_cost(label: &str, cost: &Cost) {
+    println!(
+        "{:<12} {:>12.3} {:>12.2} {:>12.3} {:>12.2} {:>10.2} {:>12.2}",
+        label, cost.energy, cost.energy_cost, cost.feed_in, cost.feed_in_compensation, cost.fixed, cost.total,
+    );
+}
+
+fn share(share: &PhaseShare) -> String {
+    share.share.map_or("-".to_string(), |share| format!("{:.1}", share * 100.0))
+}
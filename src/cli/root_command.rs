@@ -1,6 +1,10 @@
 use clap_derive::{Parser, Subcommand};
 use crate::cli::database::DatabaseCommand;
+use crate::cli::export::ExportCommand;
+use crate::cli::import::ImportCommand;
+use crate::cli::import_prices::ImportPricesCommand;
 use crate::cli::ports::ListPortsCommand;
+use crate::cli::report::ReportCommand;
 use crate::cli::start::StartCommand;
 
 /// Rusty Power Meter - Copyright (c) 2024 Florian Gäbler
@@ -14,7 +18,11 @@ pub struct RootCommand {
 #[derive(Clone, Subcommand)]
 pub enum Commands {
     Database(DatabaseCommand),
+    Export(ExportCommand),
+    Import(ImportCommand),
+    ImportPrices(ImportPricesCommand),
     ListPorts(ListPortsCommand),
+    Report(ReportCommand),
     Start(StartCommand),
 }
 
@@ -22,7 +30,11 @@ impl RootCommand {
     pub fn run(self) -> Result<(), anyhow::Error> {
         match self.command {
             Commands::Database(command) => command.run(),
+            Commands::Export(command) => command.run(),
+            Commands::Import(command) => command.run(),
+            Commands::ImportPrices(command) => command.run(),
             Commands::ListPorts(command) => command.run(),
+            Commands::Report(command) => command.run(),
             Commands::Start(command) => command.run(),
         }
     }
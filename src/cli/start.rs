@@ -1,28 +1,88 @@
+use std::sync::Arc;
 use std::thread;
+use std::time::SystemTime;
 use anyhow::Error;
 use clap_derive::{Args};
+use crate::alerts::{AlertEngine, Notifier};
+use crate::config::ConfigArgs;
 use crate::core_loop::CoreLoop;
-use crate::database::Database;
-use crate::server::Server;
+use crate::demand::DemandTracker;
+use crate::influx::InfluxExporter;
+use crate::mqtt::MqttPublisher;
+use crate::server::{Live, Server};
+use crate::storage::StorageArgs;
 
 #[derive(Clone, Args)]
 pub struct StartCommand { 
     #[arg(long)]
     port: String,
+
+    /// Name under which the readings are stored, to tell meters apart.
+    #[arg(long, default_value = "default")]
+    meter: String,
     
     #[arg(long, default_value = "false")]
     verbose: bool,
+
+    /// Minutes of readings kept in memory for `/api/recent`.
+    #[arg(long, default_value = "15")]
+    recent_minutes: u32,
+
+    #[command(flatten)]
+    storage: StorageArgs,
+
+    #[command(flatten)]
+    config: ConfigArgs,
 }
 
 impl StartCommand {
     pub fn run(self) -> Result<(), Error> {
-        let database = Database::load()?;
+        let config = Arc::new(self.config.load()?);
+        let storage = self.storage.open()?;
+
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
+        let notifier = Notifier::start(&config.alerts, storage.background_writer()?);
+        let demand_tracker = DemandTracker::new(storage.reader.as_ref(), &self.meter, config.demand, notifier.clone(), now)?;
+        let live_demand = demand_tracker.state();
+
+        let alert_engine = if config.alerts.rules.is_empty() {
+            None
+        } else {
+            Some(AlertEngine::new(&self.meter, &config.alerts, notifier))
+        };
+        let mqtt_publisher = MqttPublisher::connect(&config.mqtt, &self.meter);
+        let influx_exporter = InfluxExporter::new(&config.influx)?;
+
+        let mut core_loop = CoreLoop::new(self.port, self.meter, self.verbose, i64::from(self.recent_minutes) * 60, storage.writer.as_ref());
+        let live = Live {
+            latest_reading: core_loop.get_latest_reading(),
+            recent_readings: core_loop.get_recent_readings(),
+            readings: core_loop.get_live_readings(),
+            demand: live_demand,
+            reader_metrics: core_loop.get_metrics(),
+        };
+
+        let readings = core_loop.subscribe();
+        thread::spawn(move || demand_tracker.enter(readings));
+
+        if let Some(alert_engine) = alert_engine {
+            let readings = core_loop.subscribe();
+            thread::spawn(move || alert_engine.enter(readings));
+        }
+
+        if let Some(mqtt_publisher) = mqtt_publisher {
+            let readings = core_loop.subscribe();
+            thread::spawn(move || mqtt_publisher.enter(readings));
+        }
 
-        let core_loop = CoreLoop::new(self.port, self.verbose, &database);
-        let latest_reading_cell = core_loop.get_latest_reading_cell();
+        if let Some(influx_exporter) = influx_exporter {
+            let readings = core_loop.subscribe();
+            thread::spawn(move || influx_exporter.enter(readings));
+        }
         
+        let store = storage.reader;
         let server_thread = thread::spawn(|| {
-            Server::create(3000, latest_reading_cell).enter()
+            Server::create(3000, live, store, config).enter()
         });
         
         core_loop.enter()?;
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Error};
+use clap_derive::Args;
+use serde::Deserialize;
+
+use crate::alerts::AlertConfig;
+use crate::demand::DemandConfig;
+use crate::influx::InfluxConfig;
+use crate::mqtt::MqttConfig;
+use crate::tariff::Tariff;
+
+/// Settings which are too elaborate for command line arguments, read from a TOML file.
+///
+/// Every section is optional, so a missing file is the same as an empty one.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub tariff: Option<Tariff>,
+    #[serde(default)]
+    pub demand: DemandConfig,
+    #[serde(default)]
+    pub alerts: AlertConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub influx: InfluxConfig,
+}
+
+/// Selects the configuration file, shared by all subcommands which need one.
+#[derive(Clone, Args)]
+pub struct ConfigArgs {
+    /// Configuration file. Defaults to `config.toml` in the `rusty-power-meter` config directory.
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+impl ConfigArgs {
+    pub fn load(self) -> Result<Config, Error> {
+        let path = match &self.config {
+            Some(path) => path.clone(),
+            None => match dirs::config_dir() {
+                Some(path) => path.join("rusty-power-meter").join("config.toml"),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            // only an explicitly given file has to exist.
+            Err(error) if error.kind() == ErrorKind::NotFound && self.config.is_none() => return Ok(Config::default()),
+            Err(error) => return Err(anyhow!("Could not read {}: {error}", path.display())),
+        };
+
+        let config: Config = toml::from_str(&content).map_err(|error| anyhow!("Invalid config {}: {error}", path.display()))?;
+
+        if let Some(tariff) = &config.tariff {
+            tariff.validate()?;
+        }
+
+        config.alerts.validate()?;
+        config.mqtt.validate()?;
+        config.influx.validate()?;
+
+        Ok(config)
+    }
+}
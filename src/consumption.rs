@@ -0,0 +1,305 @@
+use anyhow::{bail, Error};
+use serde::Serialize;
+
+use crate::storage::{Order, ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::{Interval, TimeRange};
+use crate::unit::Unit;
+
+/// Readings further apart than this are considered a gap. The energy in between is still
+/// distributed evenly over the gap, but the affected intervals are flagged as interpolated.
+const MAX_GAP_SECS: i64 = 15 * 60;
+
+/// Upper bound for the number of intervals of a single report.
+const MAX_INTERVALS: usize = 100_000;
+
+/// The energy used within one interval.
+#[derive(Serialize)]
+pub struct Consumption {
+    pub from: i64,
+    pub to: i64,
+    pub energy: f64,
+    /// Energy fed into the grid, estimated from readings with a negative total power.
+    pub feed_in: f64,
+    /// Fraction of the interval for which the energy is known. Less than 1 at the edges of the
+    /// recorded data and around counter resets.
+    pub coverage: f64,
+    /// Whether some of the energy was spread over a gap between two readings.
+    pub interpolated: bool,
+    /// Number of times the counter went backwards, e.g. because the meter was replaced.
+    pub resets: u32,
+}
+
+#[derive(Serialize)]
+pub struct ConsumptionReport {
+    pub meter: String,
+    pub interval: Interval,
+    pub unit: Option<Unit>,
+    pub total: f64,
+    pub intervals: Vec<Consumption>,
+}
+
+/// An interval still being summed up.
+struct Bucket {
+    start: i64,
+    end: i64,
+    energy: f64,
+    feed_in: f64,
+    covered: i64,
+    interpolated: bool,
+    resets: u32,
+}
+
+/// Computes the energy used per interval from the differences of consecutive counter readings.
+///
+/// The energy between two readings is assumed to be used evenly, so readings on both sides of an
+/// interval boundary are split proportionally. A counter going backwards counts as reset and
+/// the energy between those two readings is unknown, so it is left out.
+pub fn consumption(store: &dyn ReadingStore, range: &TimeRange, meter: &str, interval: Interval) -> Result<ConsumptionReport, Error> {
+    let query = ReadingQuery {
+        meter: Some(meter.to_string()),
+        ..ReadingQuery::new(*range)
+    };
+
+    // the readings right outside of the range are needed to split their energy at the range boundaries.
+    let before = ReadingQuery {
+        range: TimeRange::new(None, Some(range.from))?,
+        meter: query.meter.clone(),
+        limit: Some(1),
+        order: Order::Desc,
+    };
+    let after = ReadingQuery {
+        range: TimeRange::new(Some(range.to), None)?,
+        meter: query.meter.clone(),
+        limit: Some(1),
+        order: Order::Asc,
+    };
+
+    let readings = store.readings(&before)?
+        .chain(store.readings(&query)?)
+        .chain(store.readings(&after)?);
+
+    let mut unit = None;
+    let mut buckets = Buckets { interval, buckets: Vec::new() };
+    let mut previous_counter: Option<(i64, f64)> = None;
+    let mut previous_power: Option<(i64, f64)> = None;
+
+    for reading in readings {
+        let StoredReading { timestamp, reading, .. } = reading?;
+
+        // power is held until the next reading, but not over gaps.
+        if let Some((previous_timestamp, power)) = previous_power.take() {
+            let start = previous_timestamp.max(range.from);
+            let end = timestamp.min(range.to);
+
+            if power < 0.0 && start < end && timestamp - previous_timestamp <= MAX_GAP_SECS {
+                buckets.distribute(start, end, |bucket, overlap| bucket.feed_in -= power * overlap as f64 / 3600.0)?;
+            }
+        }
+
+        if let Some(power) = reading.total_power() {
+            previous_power = Some((timestamp, power as f64));
+        }
+
+        let Some(value) = reading.meter_reading else {
+            continue;
+        };
+
+        unit = unit.or(reading.meter_reading_unit);
+
+        let Some((previous_timestamp, previous_value)) = previous_counter.replace((timestamp, value)) else {
+            continue;
+        };
+
+        let start = previous_timestamp.max(range.from);
+        let end = timestamp.min(range.to);
+        if start >= end {
+            continue;
+        }
+
+        if value < previous_value {
+            buckets.distribute(start, end, |_, _| {})?;
+            if timestamp < range.to {
+                buckets.buckets.last_mut().unwrap().resets += 1;
+            }
+
+            continue;
+        }
+
+        let rate = (value - previous_value) / (timestamp - previous_timestamp) as f64;
+        let interpolated = timestamp - previous_timestamp > MAX_GAP_SECS;
+
+        buckets.distribute(start, end, |bucket, overlap| {
+            bucket.energy += rate * overlap as f64;
+            bucket.covered += overlap;
+            bucket.interpolated |= interpolated;
+        })?;
+    }
+
+    let intervals = buckets.buckets.into_iter()
+        .map(|bucket| {
+            let from = bucket.start.max(range.from);
+            let to = bucket.end.min(range.to);
+
+            Consumption {
+                from,
+                to,
+                energy: bucket.energy,
+                feed_in: bucket.feed_in,
+                coverage: bucket.covered as f64 / (to - from) as f64,
+                interpolated: bucket.interpolated,
+                resets: bucket.resets,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(ConsumptionReport {
+        meter: meter.to_string(),
+        interval,
+        unit,
+        total: intervals.iter().map(|consumption| consumption.energy).sum(),
+        intervals,
+    })
+}
+
+/// The intervals of a report, created as readings come in.
+struct Buckets {
+    interval: Interval,
+    buckets: Vec<Bucket>,
+}
+
+impl Buckets {
+    /// Calls `f` with every interval overlapping `start..end` and the seconds of the overlap.
+    fn distribute(&mut self, start: i64, end: i64, mut f: impl FnMut(&mut Bucket, i64)) -> Result<(), Error> {
+        if self.buckets.is_empty() {
+            let start = self.interval.start_of(start)?;
+            self.buckets.push(Bucket::new(start, self.interval.next(start)?));
+        }
+
+        while self.buckets[0].start > start {
+            let end = self.buckets[0].start;
+            self.buckets.insert(0, Bucket::new(self.interval.start_of(end - 1)?, end));
+        }
+
+        while self.buckets.last().unwrap().end < end {
+            if self.buckets.len() == MAX_INTERVALS {
+                bail!("Too many intervals, choose a shorter time range or a longer interval.");
+            }
+
+            let start = self.buckets.last().unwrap().end;
+            self.buckets.push(Bucket::new(start, self.interval.next(start)?));
+        }
+
+        let first = self.buckets.partition_point(|bucket| bucket.end <= start);
+        for bucket in self.buckets[first..].iter_mut().take_while(|bucket| bucket.start < end) {
+            let overlap = end.min(bucket.end) - start.max(bucket.start);
+            f(bucket, overlap);
+        }
+
+        Ok(())
+    }
+}
+
+impl Bucket {
+    fn new(start: i64, end: i64) -> Self {
+        Bucket {
+            start,
+            end,
+            energy: 0.0,
+            feed_in: 0.0,
+            covered: 0,
+            interpolated: false,
+            resets: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::meter_reading::MeterReading;
+    use crate::storage::MemoryStore;
+    use crate::timestamp::{local_timestamp, use_test_time_zone};
+
+    /// Unix seconds of local midnight.
+    fn midnight(year: i32, month: u32, day: u32) -> i64 {
+        use_test_time_zone();
+        local_timestamp(NaiveDate::from_ymd_opt(year, month, day).unwrap().and_time(Default::default())).unwrap()
+    }
+
+    fn insert(store: &MemoryStore, readings: &[(i64, f64)]) {
+        for &(timestamp, value) in readings {
+            let reading = MeterReading { meter_reading: Some(value), meter_reading_unit: Some(Unit::WattHour), ..Default::default() };
+            store.insert("grid", timestamp, &reading).unwrap();
+        }
+    }
+
+    /// A counter going up by 1 Wh per second, read every 15 minutes from `from` to `to`.
+    fn steady(from: i64, to: i64) -> MemoryStore {
+        let store = MemoryStore::default();
+        let readings = (from..=to).step_by(900).map(|timestamp| (timestamp, (timestamp - from) as f64)).collect::<Vec<_>>();
+        insert(&store, &readings);
+
+        store
+    }
+
+    fn report(store: &MemoryStore, from: i64, to: i64, interval: Interval) -> ConsumptionReport {
+        consumption(store, &TimeRange { from, to }, "grid", interval).unwrap()
+    }
+
+    #[test]
+    fn leaves_out_the_energy_around_a_counter_reset() {
+        let start = midnight(2024, 3, 1);
+        let store = MemoryStore::default();
+        insert(&store, &[(0, 1000.0), (900, 1100.0), (1800, 1200.0), (2700, 0.0), (3600, 100.0), (4500, 200.0), (5400, 300.0), (6300, 400.0), (7200, 500.0)]
+            .map(|(offset, value)| (start + offset, value)));
+
+        let report = report(&store, start, start + 7200, Interval::Hour);
+
+        let intervals = report.intervals.iter().map(|hour| (hour.energy, hour.coverage, hour.resets)).collect::<Vec<_>>();
+        assert_eq!(intervals, [(300.0, 0.75, 1), (400.0, 1.0, 0)]);
+        assert_eq!(report.total, 700.0);
+        assert_eq!(report.unit, Some(Unit::WattHour));
+    }
+
+    #[test]
+    fn spreads_the_energy_over_a_gap() {
+        let start = midnight(2024, 3, 1);
+        let store = MemoryStore::default();
+        insert(&store, &[(0, 0.0), (900, 900.0), (8100, 8100.0), (9000, 9000.0)].map(|(offset, value)| (start + offset, value)));
+
+        let report = report(&store, start, start + 3 * 3600, Interval::Hour);
+
+        let intervals = report.intervals.iter().map(|hour| (hour.energy, hour.coverage, hour.interpolated)).collect::<Vec<_>>();
+        assert_eq!(intervals, [(3600.0, 1.0, true), (3600.0, 1.0, true), (1800.0, 0.5, true)]);
+    }
+
+    #[test]
+    fn follows_the_local_clock_on_daylight_saving_days() {
+        for (day, next_day, hours) in [(midnight(2024, 3, 31), midnight(2024, 4, 1), 23), (midnight(2024, 10, 27), midnight(2024, 10, 28), 25)] {
+            assert_eq!(next_day - day, hours * 3600);
+            let store = steady(day - 3600, next_day + 3600);
+
+            let daily = report(&store, day, next_day, Interval::Day);
+            let intervals = daily.intervals.iter().map(|day| (day.from, day.to, day.energy, day.coverage)).collect::<Vec<_>>();
+            assert_eq!(intervals, [(day, next_day, (hours * 3600) as f64, 1.0)]);
+
+            let hourly = report(&store, day, next_day, Interval::Hour);
+            assert_eq!(hourly.intervals.len(), hours as usize);
+            assert!(hourly.intervals.iter().all(|hour| hour.to - hour.from == 3600 && hour.energy == 3600.0));
+        }
+    }
+
+    #[test]
+    fn sums_up_a_month_with_a_daylight_saving_day() {
+        let (march, april) = (midnight(2024, 3, 1), midnight(2024, 4, 1));
+        let store = steady(march - 3600, april + 3600);
+
+        let report = report(&store, march - 86_400, april + 86_400, Interval::Month);
+
+        let intervals = report.intervals.iter().map(|month| (month.from, month.to, month.energy)).collect::<Vec<_>>();
+        assert_eq!(intervals[1], (march, april, ((31 * 24 - 1) * 3600) as f64));
+        assert_eq!(report.total, (april - march + 2 * 3600) as f64);
+    }
+}
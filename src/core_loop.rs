@@ -1,25 +1,59 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use serialport::{Parity, StopBits};
-use crate::database::Database;
+use crate::database::is_locked;
+use crate::storage::{ReadingStore, StoredReading};
 use crate::meter_reading::MeterReading;
-use std::io::{Read};
-use std::sync::{Arc};
+use crate::latest_reading::{LatestReading, RecentReadings};
+use std::io::{BufReader, ErrorKind, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use anyhow::Error;
-use crossbeam_utils::atomic::AtomicCell;
+use tokio::sync::broadcast;
+
+/// Readings buffered per live subscriber, a subscriber falling further behind skips the oldest ones.
+const LIVE_READINGS_CAPACITY: usize = 16;
+
+/// Counters of what the reader received from the meter, e.g. to tell a bad connection apart from a silent meter.
+#[derive(Default)]
+pub struct ReaderMetrics {
+    /// SML files decoded from the serial port.
+    pub frames: AtomicU64,
+    /// Bytes the transport decoder rejected.
+    pub transport_errors: AtomicU64,
+    /// Decoded files which weren't valid SML or didn't contain a reading.
+    pub parse_errors: AtomicU64,
+    pub readings: AtomicU64,
+    /// Readings dropped because there already was one at the same timestamp.
+    pub duplicates: AtomicU64,
+    /// Readings dropped because the store was locked by another process, e.g. during a restore.
+    pub locked: AtomicU64,
+}
 
 pub struct CoreLoop<'a> { 
     port: String,
-    database: &'a Database,
-    latest_reading: Arc<AtomicCell<Option<MeterReading>>>,
+    meter: String,
+    store: &'a dyn ReadingStore,
+    latest_reading: Arc<LatestReading>,
+    recent_readings: Arc<RecentReadings>,
+    subscribers: Vec<Sender<StoredReading>>,
+    live_readings: broadcast::Sender<StoredReading>,
+    metrics: Arc<ReaderMetrics>,
     verbose: bool
 }
 
 impl<'a> CoreLoop<'a> {
-    pub fn new(port: String, verbose: bool, database: &'a Database) -> Self {
+    /// Keeps the readings of the last `recent_retention` seconds in memory, see `get_recent_readings`.
+    pub fn new(port: String, meter: String, verbose: bool, recent_retention: i64, store: &'a dyn ReadingStore) -> Self {
         Self {
             port,
-            database,
-            latest_reading: Arc::new(AtomicCell::new(None)),
+            meter,
+            store,
+            latest_reading: Arc::new(LatestReading::default()),
+            recent_readings: Arc::new(RecentReadings::new(recent_retention)),
+            subscribers: Vec::new(),
+            live_readings: broadcast::channel(LIVE_READINGS_CAPACITY).0,
+            metrics: Arc::new(ReaderMetrics::default()),
             verbose
         }
     }
@@ -35,17 +69,26 @@ impl<'a> CoreLoop<'a> {
         
         // let mut current_ball_position = 1;
         let mut decoder = sml_rs::transport::Decoder::<Vec<u8>>::new();
+        let mut registered_units = None;
         
         println!("Now listening for SML messages on {}...", self.port);
 
-        for res in port.bytes() {
-            let byte = res?;
+        for res in BufReader::new(port).bytes() {
+            let byte = match res {
+                Ok(byte) => byte,
+                // a meter falling silent is reported by the alert rules, so just keep waiting.
+                Err(error) if error.kind() == ErrorKind::TimedOut => continue,
+                Err(error) => return Err(error.into()),
+            };
 
             match decoder.push_byte(byte) {
                 Ok(None) => {}
                 Ok(Some(decoded_bytes)) => {
+                    self.metrics.frames.fetch_add(1, Ordering::Relaxed);
+
                     let result = sml_rs::parser::complete::parse(decoded_bytes);
                     let Ok(sml_file) = result else {
+                        self.metrics.parse_errors.fetch_add(1, Ordering::Relaxed);
                         if self.verbose {
                             println!("Err({:?})", result);
                         }
@@ -54,6 +97,7 @@ impl<'a> CoreLoop<'a> {
 
                     let reading = MeterReading::parse(sml_file);
                     let Ok(reading) = reading else {
+                        self.metrics.parse_errors.fetch_add(1, Ordering::Relaxed);
                         continue;
                     };
 
@@ -62,13 +106,49 @@ impl<'a> CoreLoop<'a> {
                         println!("{}", reading.display_compact());
                     }
                     
-                    self.database.insert_reading(&reading)?;
-                    self.latest_reading.store(Some(reading));
+                    // the units rarely change, so only write them when they do.
+                    if registered_units.as_ref() != Some(&reading.units()) {
+                        match self.store.register_units(&self.meter, &reading) {
+                            Ok(()) => registered_units = Some(reading.units()),
+                            // tried again with the next reading.
+                            Err(error) if is_locked(&error) => println!("Warning: Could not register the units: {error}"),
+                            Err(error) => return Err(error),
+                        }
+                    }
+
+                    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
+                    match self.store.insert(&self.meter, timestamp, &reading) {
+                        Ok(true) => {
+                            self.metrics.readings.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(false) => {
+                            self.metrics.duplicates.fetch_add(1, Ordering::Relaxed);
+                            println!("Warning: Duplicate timestamp.");
+                        }
+                        // the meter sends a new reading every few seconds, so this one is skipped rather than retried.
+                        Err(error) if is_locked(&error) => {
+                            self.metrics.locked.fetch_add(1, Ordering::Relaxed);
+                            println!("Warning: Skipped a reading: {error}");
+                        }
+                        Err(error) => return Err(error),
+                    }
+
+                    let stored_reading = StoredReading { timestamp, meter: self.meter.clone(), reading: reading.clone() };
+                    for subscriber in &self.subscribers {
+                        // a subscriber which went away just doesn't get any more readings.
+                        let _ = subscriber.send(stored_reading.clone());
+                    }
+                    // fails only while nobody is listening.
+                    let _ = self.live_readings.send(stored_reading.clone());
+
+                    self.recent_readings.push(stored_reading.clone());
+                    self.latest_reading.update(stored_reading);
                     
                     
                     // print_progress_bar(&mut current_ball_position);
                 }
                 Err(e) => {
+                    self.metrics.transport_errors.fetch_add(1, Ordering::Relaxed);
                     if self.verbose {
                         println!("Err({:?})", e);
                     }
@@ -79,7 +159,27 @@ impl<'a> CoreLoop<'a> {
         Ok(())
     }
     
-    pub fn get_latest_reading_cell(&self) -> Arc<AtomicCell<Option<MeterReading>>> {
+    /// Returns a channel receiving every reading after it has been stored.
+    pub fn subscribe(&mut self) -> Receiver<StoredReading> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Returns the sender of the readings, to subscribe to them from async code any number of times.
+    pub fn get_live_readings(&self) -> broadcast::Sender<StoredReading> {
+        self.live_readings.clone()
+    }
+
+    pub fn get_latest_reading(&self) -> Arc<LatestReading> {
         self.latest_reading.clone()
     }
+
+    pub fn get_recent_readings(&self) -> Arc<RecentReadings> {
+        self.recent_readings.clone()
+    }
+
+    pub fn get_metrics(&self) -> Arc<ReaderMetrics> {
+        self.metrics.clone()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,258 @@
+use anyhow::{anyhow, bail, Error};
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta};
+use clap_derive::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::consumption::{consumption, Consumption};
+use crate::prices::SpotPrices;
+use crate::storage::ReadingStore;
+use crate::tariff::Tariff;
+use crate::timestamp::{format_timestamp, local_timestamp, Interval, TimeRange};
+use crate::unit::Unit;
+
+#[derive(Clone, Copy, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostPeriod {
+    Day,
+    Month,
+    /// The billing periods of the tariff, see `Tariff::billing_period`.
+    Billing,
+}
+
+/// The money spent (and earned) within one period. Energies are in kWh.
+#[derive(Default, Serialize)]
+pub struct Cost {
+    pub from: i64,
+    pub to: i64,
+    pub energy: f64,
+    pub energy_cost: f64,
+    pub feed_in: f64,
+    pub feed_in_compensation: f64,
+    /// The monthly fee, prorated to the time covered by this period.
+    pub fixed: f64,
+    /// `energy_cost + fixed - feed_in_compensation`
+    pub total: f64,
+}
+
+#[derive(Serialize)]
+pub struct CostReport {
+    pub meter: String,
+    pub currency: String,
+    pub period: CostPeriod,
+    pub total: Cost,
+    pub periods: Vec<Cost>,
+}
+
+/// Prices the hourly consumption with the tariff and sums it up per period.
+///
+/// Within an hour the energy is assumed to be used evenly, so an hour in which the price changes
+/// is billed at the average price of its minutes. `prices` are needed for windows using spot prices.
+pub fn cost(
+    store: &dyn ReadingStore,
+    tariff: &Tariff,
+    prices: &SpotPrices,
+    range: &TimeRange,
+    meter: &str,
+    period: CostPeriod,
+) -> Result<CostReport, Error> {
+    let report = consumption(store, range, meter, Interval::Hour)?;
+    if report.unit.as_ref().is_some_and(|unit| *unit != Unit::WattHour) {
+        bail!("Can't price readings in {:?}.", report.unit);
+    }
+
+    let mut periods = Vec::<Cost>::new();
+    let mut period_end = i64::MIN;
+    for hour in &report.intervals {
+        if hour.from >= period_end {
+            period_end = period_end_of(tariff, period, hour.from)?;
+            periods.push(Cost { from: hour.from, ..Default::default() });
+        }
+
+        let cost = periods.last_mut().unwrap();
+        add_hour(tariff, prices, cost, hour)?;
+        cost.to = hour.to;
+    }
+
+    let mut total = Cost {
+        from: periods.first().map_or(range.from, |cost| cost.from),
+        to: periods.last().map_or(range.to, |cost| cost.to),
+        ..Default::default()
+    };
+
+    for cost in &periods {
+        total.energy += cost.energy;
+        total.energy_cost += cost.energy_cost;
+        total.feed_in += cost.feed_in;
+        total.feed_in_compensation += cost.feed_in_compensation;
+        total.fixed += cost.fixed;
+        total.total += cost.total;
+    }
+
+    Ok(CostReport {
+        meter: report.meter,
+        currency: tariff.currency.clone(),
+        period,
+        total,
+        periods,
+    })
+}
+
+fn add_hour(tariff: &Tariff, prices: &SpotPrices, cost: &mut Cost, hour: &Consumption) -> Result<(), Error> {
+    let start = local_date_time(hour.from)?;
+    let minutes = ((hour.to - hour.from) / 60).max(1);
+
+    let mut price = 0.0;
+    for minute in 0..minutes {
+        let window = tariff.window_at(start + TimeDelta::minutes(minute))?;
+        price += window.price;
+
+        if window.spot {
+            let timestamp = hour.from + minute * 60;
+            price += prices.at(timestamp).ok_or_else(|| anyhow!("No spot price for {}.", format_timestamp(timestamp)))?;
+        }
+    }
+    price /= minutes as f64;
+
+    let tariff_period = tariff.period_at(start.date()).ok_or_else(|| anyhow!("The tariff has no price for {}.", start.date()))?;
+
+    let month_start = Interval::Month.start_of(hour.from)?;
+    let month_length = Interval::Month.next(month_start)? - month_start;
+    let fixed = tariff_period.monthly_fee * (hour.to - hour.from) as f64 / month_length as f64;
+
+    let energy = hour.energy / 1000.0;
+    let feed_in = hour.feed_in / 1000.0;
+
+    cost.energy += energy;
+    cost.energy_cost += energy * price;
+    cost.feed_in += feed_in;
+    cost.feed_in_compensation += feed_in * tariff_period.feed_in;
+    cost.fixed += fixed;
+    cost.total += energy * price + fixed - feed_in * tariff_period.feed_in;
+
+    Ok(())
+}
+
+/// Returns the end of the period containing `timestamp`.
+fn period_end_of(tariff: &Tariff, period: CostPeriod, timestamp: i64) -> Result<i64, Error> {
+    match period {
+        CostPeriod::Day => Interval::Day.next(Interval::Day.start_of(timestamp)?),
+        CostPeriod::Month => Interval::Month.next(Interval::Month.start_of(timestamp)?),
+        CostPeriod::Billing => {
+            let (_, end) = tariff.billing_period(local_date_time(timestamp)?.date())?;
+            local_timestamp(end.and_time(Default::default()))
+        }
+    }
+}
+
+fn local_date_time(timestamp: i64) -> Result<NaiveDateTime, Error> {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|date_time| date_time.with_timezone(&Local).naive_local())
+        .ok_or_else(|| anyhow!("Invalid timestamp {timestamp}."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meter_reading::MeterReading;
+    use crate::storage::MemoryStore;
+    use crate::timestamp::use_test_time_zone;
+
+    const TARIFF: &str = r#"
+        [[periods]]
+        monthly_fee = 12.5
+        feed_in = 0.082
+        windows = [{ days = ["weekday"], from = "06:00", to = "22:00", price = 0.35 }, { price = 0.25 }]
+
+        [[periods]]
+        from = "2025-01-01"
+        monthly_fee = 14.0
+        windows = [{ price = 0.31 }]
+    "#;
+
+    fn local(date_time: &str) -> i64 {
+        use_test_time_zone();
+        local_timestamp(NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    /// Readings every 15 minutes from `from` to `to`, with the counter going up by `rate` Wh per second.
+    fn store(from: &str, to: &str, rate: f64, line_one: i32) -> MemoryStore {
+        let store = MemoryStore::default();
+        let from = local(from);
+
+        for timestamp in (from..=local(to)).step_by(900) {
+            let reading = MeterReading {
+                meter_reading: Some(rate * (timestamp - from) as f64),
+                meter_reading_unit: Some(Unit::WattHour),
+                line_one: Some(line_one),
+                line_one_unit: Some(Unit::Watt),
+                ..Default::default()
+            };
+            store.insert("grid", timestamp, &reading).unwrap();
+        }
+
+        store
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    /// The monthly fee for `hours` of a month with `days`.
+    fn fee(monthly_fee: f64, hours: f64, days: f64) -> f64 {
+        monthly_fee * hours / (days * 24.0)
+    }
+
+    #[test]
+    fn prices_every_hour_with_its_window_and_period() {
+        let tariff = toml::from_str::<Tariff>(TARIFF).unwrap();
+        let store = store("2024-12-31 20:00", "2025-01-01 03:00", 1.0, 3600);
+        let range = TimeRange { from: local("2024-12-31 21:00"), to: local("2025-01-01 02:00") };
+
+        let report = cost(&store, &tariff, &SpotPrices::default(), &range, "grid", CostPeriod::Day).unwrap();
+
+        let [december, january] = &report.periods[..] else {
+            panic!("Expected two days.");
+        };
+        assert_eq!((december.from, december.to), (range.from, local("2025-01-01 00:00")));
+        assert_close(december.energy, 3.0 * 3.6);
+        assert_close(december.energy_cost, 3.6 * 0.35 + 2.0 * 3.6 * 0.25);
+        assert_close(december.fixed, fee(12.5, 3.0, 31.0));
+
+        assert_eq!((january.from, january.to), (local("2025-01-01 00:00"), range.to));
+        assert_close(january.energy_cost, 2.0 * 3.6 * 0.31);
+        assert_close(january.fixed, fee(14.0, 2.0, 31.0));
+        assert_close(january.total, january.energy_cost + january.fixed);
+
+        assert_close(report.total.energy, 5.0 * 3.6);
+        assert_close(report.total.total, december.total + january.total);
+    }
+
+    #[test]
+    fn averages_the_price_of_an_hour_by_the_minute() {
+        let tariff = toml::from_str::<Tariff>(r#"periods = [{ windows = [{ from = "06:30", to = "22:00", price = 0.4 }, { price = 0.2 }] }]"#).unwrap();
+        let store = store("2024-06-03 05:00", "2024-06-03 08:00", 1.0, 3600);
+        let range = TimeRange { from: local("2024-06-03 06:00"), to: local("2024-06-03 07:00") };
+
+        let report = cost(&store, &tariff, &SpotPrices::default(), &range, "grid", CostPeriod::Day).unwrap();
+
+        assert_close(report.total.energy_cost, 3.6 * 0.3);
+    }
+
+    #[test]
+    fn credits_the_feed_in_and_prorates_the_monthly_fee() {
+        let tariff = toml::from_str::<Tariff>(TARIFF).unwrap();
+        // 1.8 kW are fed in while the counter stands still.
+        let store = store("2024-06-01 11:45", "2024-06-01 14:15", 0.0, -1800);
+        let range = TimeRange { from: local("2024-06-01 12:00"), to: local("2024-06-01 14:00") };
+
+        let report = cost(&store, &tariff, &SpotPrices::default(), &range, "grid", CostPeriod::Month).unwrap();
+
+        assert_eq!(report.periods.len(), 1);
+        let total = &report.total;
+        assert_close(total.energy, 0.0);
+        assert_close(total.feed_in, 3.6);
+        assert_close(total.feed_in_compensation, 3.6 * 0.082);
+        assert_close(total.fixed, fee(12.5, 2.0, 30.0));
+        assert_close(total.total, total.fixed - total.feed_in_compensation);
+    }
+}
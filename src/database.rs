@@ -1,15 +1,51 @@
+use std::ffi::{c_char, c_int, c_void, CStr};
 use std::fmt::Display;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{bail, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use serde::Serialize;
-use sqlite::{Connection, ConnectionThreadSafe, OpenFlags, Row, Type};
+use sqlite::{Connection, ConnectionThreadSafe, OpenFlags, State, Type};
+use sqlite3_sys as ffi;
 
+use crate::alerts::Alert;
 use crate::meter_reading::MeterReading;
+use crate::prices::SpotPrice;
+use crate::storage::{Aggregate, Order, ReadingQuery, StoredReading};
+use crate::timestamp::TimeRange;
 use crate::unit::Unit;
 
+/// How long a connection waits for a lock held by another connection (e.g. during a backup) before failing.
+const BUSY_TIMEOUT_MS: usize = 10_000;
+
+/// Pages copied by a backup or freed by a vacuum at once, before the lock is given to other connections.
+const PAGES_PER_STEP: c_int = 1024;
+
+/// How long a backup or vacuum pauses between two steps, long enough for a waiting writer to get the lock.
+const STEP_PAUSE: Duration = Duration::from_millis(150);
+
+/// How long a statement of `ReadonlyDatabase::query` may run.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Rows returned by `ReadonlyDatabase::query`, further rows are cut off.
+const MAX_QUERY_ROWS: usize = 10_000;
+
+/// Bytes of values returned by `ReadonlyDatabase::query`, further rows are cut off. Also the
+/// longest string or blob SQLite builds for it, so `zeroblob` or `randomblob` can't allocate more.
+const MAX_QUERY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Virtual machine instructions between two checks whether a query has to stop.
+const PROGRESS_INTERVAL: c_int = 10_000;
+
+/// Pragmas which take an argument but only read.
+const READONLY_PRAGMAS: [&str; 6] = ["table_info", "table_xinfo", "index_list", "index_info", "index_xinfo", "foreign_key_list"];
+
 pub struct Database(Connection);
 
 impl Database {
@@ -25,9 +61,14 @@ impl Database {
             bail!("Database already exists.")
         }
         
-        let connection = Connection::open(path)?;
+        Self::create(Connection::open(path)?)
+    }
 
+    /// Creates the tables of the first version, which `migrate` then brings up to date.
+    fn create(connection: Connection) -> Result<Self, anyhow::Error> {
+        // incremental vacuum has to be chosen before the first table is created.
         let statement = " \
+            PRAGMA auto_vacuum = INCREMENTAL; \
             CREATE TABLE Readings ( \
                 MeterTime INTEGER, \
                 Timestamp DATETIME NOT NULL, \
@@ -47,77 +88,523 @@ impl Database {
     pub fn load() -> Result<Self, anyhow::Error> {
         let path = Self::path()?;
 
-        if path.exists() {
+        let mut database = if path.exists() {
             let connection = Connection::open(&path)?;
-            Ok(Self(connection))
+            Self(connection)
         } else {
             fs::create_dir_all(path.parent().unwrap())?;
-            Ok(Self::init(&path)?)
+            Self::init(&path)?
+        };
+
+        database.0.set_busy_timeout(BUSY_TIMEOUT_MS)?;
+        database.migrate()?;
+        Ok(database)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self, anyhow::Error> {
+        let database = Self::create(Connection::open(":memory:")?)?;
+        database.migrate()?;
+        Ok(database)
+    }
+
+    /// Brings the schema up to date. Its version is tracked in SQLite's `user_version`.
+    fn migrate(&self) -> Result<(), anyhow::Error> {
+        let version_row = self.0.prepare("PRAGMA user_version")?.into_iter().next().ok_or(anyhow::anyhow!("No version row."))??;
+        let version = version_row.read::<i64, _>(0);
+
+        if version < 1 {
+            // readings recorded before meters were distinguished were stored in Wh and W.
+            let statement = " \
+                BEGIN; \
+                ALTER TABLE Readings ADD COLUMN Meter TEXT NOT NULL DEFAULT 'default'; \
+                CREATE TABLE Meters ( \
+                    Name TEXT PRIMARY KEY, \
+                    MeterReadingUnit TEXT, \
+                    LineOneUnit TEXT, \
+                    LineTwoUnit TEXT, \
+                    LineThreeUnit TEXT \
+                ); \
+                INSERT INTO Meters VALUES ('default', 'Wh', 'W', 'W', 'W'); \
+                PRAGMA user_version = 1; \
+                COMMIT; \
+            ";
+
+            self.0.execute(statement)?;
+        }
+
+        if version < 2 {
+            // prices per kWh of dynamic tariffs, valid from `Timestamp` for `Duration` seconds.
+            let statement = " \
+                BEGIN; \
+                CREATE TABLE Prices ( \
+                    Timestamp INTEGER PRIMARY KEY, \
+                    Duration INTEGER NOT NULL, \
+                    Price REAL NOT NULL \
+                ); \
+                PRAGMA user_version = 2; \
+                COMMIT; \
+            ";
+
+            self.0.execute(statement)?;
+        }
+
+        if version < 3 {
+            // notifications of the alert rules, `Delivered` is NULL for alerts without a webhook.
+            let statement = " \
+                BEGIN; \
+                CREATE TABLE Alerts ( \
+                    Id INTEGER PRIMARY KEY, \
+                    Timestamp INTEGER NOT NULL, \
+                    Meter TEXT NOT NULL, \
+                    Rule TEXT NOT NULL, \
+                    State TEXT NOT NULL, \
+                    Value REAL NOT NULL, \
+                    Threshold REAL NOT NULL, \
+                    Message TEXT NOT NULL, \
+                    Delivered INTEGER \
+                ); \
+                CREATE INDEX idx_alerts_timestamp ON Alerts (Timestamp); \
+                PRAGMA user_version = 3; \
+                COMMIT; \
+            ";
+
+            self.0.execute(statement)?;
+        }
+
+        if version < 4 {
+            // timestamps are only unique per meter, so meters recording in the same second don't drop each other's readings.
+            let statement = " \
+                BEGIN; \
+                DROP INDEX idx_timestamp; \
+                CREATE INDEX idx_timestamp ON Readings (Timestamp); \
+                CREATE UNIQUE INDEX idx_meter_timestamp ON Readings (Meter, Timestamp); \
+                PRAGMA user_version = 4; \
+                COMMIT; \
+            ";
+
+            self.0.execute(statement)?;
         }
+
+        Ok(())
     }
     
-    pub fn insert_reading(&self, reading: &MeterReading) -> Result<(), anyhow::Error> {
-        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
-        // let timestamp = sqlite::Value::Binary(timestamp.to_le_bytes().to_vec());
-        
-        let mut statement = self.0.prepare("INSERT INTO Readings (MeterTime, Timestamp, MeterReading, LineOne, LineTwo, LineThree) VALUES (?, ?, ?, ?, ?, ?)")?;
+    /// Inserts a reading with the given timestamp (unix seconds).
+    ///
+    /// Returns `false` without inserting anything if the meter already has a reading with the same timestamp.
+    pub fn insert_reading_at(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, anyhow::Error> {
+        let mut statement = self.0.prepare("INSERT INTO Readings (MeterTime, Timestamp, MeterReading, LineOne, LineTwo, LineThree, Meter) VALUES (?, ?, ?, ?, ?, ?, ?)")?;
         statement.bind((1, reading.meter_time.map(|x| x as i64)))?;
         statement.bind((2, timestamp))?;
         statement.bind((3, reading.meter_reading))?;
         statement.bind((4, reading.line_one.map(|x| x as i64)))?;
         statement.bind((5, reading.line_two.map(|x| x as i64)))?;
         statement.bind((6, reading.line_three.map(|x| x as i64)))?;
+        statement.bind((7, meter))?;
 
         let result = statement.next();
 
         const UNIQUE_CONSTRAINT_ERROR: isize = 19;
         if let Err(error) = result {
             if error.code == Some(UNIQUE_CONSTRAINT_ERROR) {
-                println!("Warning: Duplicate timestamp.");
-                return Ok(());
+                return Ok(false);
             }
 
             return Err(error.into());
         }
         
-        Ok(())
+        Ok(true)
     }
-    
-    pub fn list_readings<'a>(&'a self) -> Result<impl Iterator<Item = Result<MeterReading, Error>> + 'a, anyhow::Error> {
-        let statement = self.0.prepare("SELECT MeterTime, Timestamp, MeterReading, LineOne, LineTwo, LineThree FROM Readings")?;
-        
-        Ok(statement.into_iter().map(move |row| {
-            let Ok(row) = row else {
-                return Err(anyhow::anyhow!("Error reading row."));
-            };
-            
-            Ok(MeterReading {
-                meter_time: Some(row.read::<i64, _>(0) as u32),
-                meter_reading: Some(row.read::<f64, _>(0)),
-                meter_reading_unit: Some(Unit::WattHour),
-                line_one: Some(row.read::<i64, _>(1) as i32),
-                line_one_unit: Some(Unit::Watt),
-                line_two: Some(row.read::<i64, _>(2) as i32),
-                line_two_unit: Some(Unit::Watt),
-                line_three: Some(row.read::<i64, _>(3) as i32),
-                line_three_unit: Some(Unit::Watt),
-            })
-        }))
+
+    /// Runs `f` within a single transaction, which is committed if `f` succeeds and rolled back otherwise.
+    pub fn transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T, Error>) -> Result<T, Error> {
+        self.0.execute("BEGIN")?;
+
+        match f(self) {
+            Ok(value) => {
+                self.0.execute("COMMIT")?;
+                Ok(value)
+            }
+            Err(error) => {
+                self.0.execute("ROLLBACK")?;
+                Err(error)
+            }
+        }
     }
     
+    /// Records the units a meter reports. Units missing from `reading` are left unchanged.
+    pub fn update_meter_units(&self, meter: &str, reading: &MeterReading) -> Result<(), anyhow::Error> {
+        let mut statement = self.0.prepare(" \
+            INSERT INTO Meters (Name, MeterReadingUnit, LineOneUnit, LineTwoUnit, LineThreeUnit) VALUES (?, ?, ?, ?, ?) \
+            ON CONFLICT (Name) DO UPDATE SET \
+                MeterReadingUnit = COALESCE(excluded.MeterReadingUnit, MeterReadingUnit), \
+                LineOneUnit = COALESCE(excluded.LineOneUnit, LineOneUnit), \
+                LineTwoUnit = COALESCE(excluded.LineTwoUnit, LineTwoUnit), \
+                LineThreeUnit = COALESCE(excluded.LineThreeUnit, LineThreeUnit) \
+        ")?;
+        statement.bind((1, meter))?;
+        statement.bind((2, reading.meter_reading_unit.as_ref().map(Unit::as_str)))?;
+        statement.bind((3, reading.line_one_unit.as_ref().map(Unit::as_str)))?;
+        statement.bind((4, reading.line_two_unit.as_ref().map(Unit::as_str)))?;
+        statement.bind((5, reading.line_three_unit.as_ref().map(Unit::as_str)))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
+    /// Stores spot prices, replacing those already stored for the same time. Returns the number of prices stored.
+    pub fn insert_prices(&self, prices: &[SpotPrice]) -> Result<u64, anyhow::Error> {
+        self.transaction(|database| {
+            let mut statement = database.0.prepare("INSERT OR REPLACE INTO Prices (Timestamp, Duration, Price) VALUES (?, ?, ?)")?;
+
+            for price in prices {
+                statement.reset()?;
+                statement.bind((1, price.timestamp))?;
+                statement.bind((2, price.duration))?;
+                statement.bind((3, price.price))?;
+                statement.next()?;
+            }
+
+            Ok(prices.len() as u64)
+        })
+    }
+
+    pub fn insert_alert(&self, alert: &Alert, delivered: Option<bool>) -> Result<(), anyhow::Error> {
+        let mut statement = self.0.prepare(" \
+            INSERT INTO Alerts (Timestamp, Meter, Rule, State, Value, Threshold, Message, Delivered) VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+        ")?;
+        statement.bind((1, alert.timestamp))?;
+        statement.bind((2, alert.meter.as_str()))?;
+        statement.bind((3, alert.rule.as_str()))?;
+        statement.bind((4, alert.state.as_str()))?;
+        statement.bind((5, alert.value))?;
+        statement.bind((6, alert.threshold))?;
+        statement.bind((7, alert.message.as_str()))?;
+        statement.bind((8, delivered.map(i64::from)))?;
+        statement.next()?;
+
+        Ok(())
+    }
+
     pub fn metrics(&self) -> Result<DatabaseMetrics, anyhow::Error> {
         let count_stmt = self.0.prepare("SELECT COUNT(*) FROM Readings")?;
         let count_row = count_stmt.into_iter().next().ok_or(anyhow::anyhow!("No count row."))??;
         
         let count_readings = count_row.read::<i64, _>(0) as u64;
         let file_size = fs::metadata(Self::path()?)?.len();
+
+        let latest_query = ReadingQuery {
+            limit: Some(1),
+            order: Order::Desc,
+            ..ReadingQuery::new(TimeRange::new(None, None)?)
+        };
+        let latest_reading = self.readings(&latest_query)?.next().transpose()?;
         
         Ok(DatabaseMetrics {
             location: Self::path()?,
             count_readings,
             file_size,
+            latest_reading,
+        })
+    }
+
+    pub fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<impl Iterator<Item = Result<StoredReading, Error>> + 'a, Error> {
+        query_readings(&self.0, query)
+    }
+
+    pub fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        query_aggregates(&self.0, range, meter, bucket_secs)
+    }
+
+    /// The size of the database in bytes.
+    pub fn size(&self) -> Result<u64, anyhow::Error> {
+        database_size(&self.0)
+    }
+
+    /// Runs SQLite's integrity check and returns the problems found, which is just `"ok"` for a healthy database.
+    pub fn integrity_check(&self) -> Result<Vec<String>, anyhow::Error> {
+        integrity_check(&self.0)
+    }
+
+    /// Lists the periods longer than `min_secs` in which a meter recorded no readings.
+    pub fn gaps(&self, min_secs: i64) -> Result<Vec<Gap>, anyhow::Error> {
+        let mut statement = self.0.prepare(" \
+            SELECT Meter, Previous, Timestamp FROM ( \
+                SELECT Meter, LAG(Timestamp) OVER (PARTITION BY Meter ORDER BY Timestamp) AS Previous, Timestamp FROM Readings \
+            ) \
+            WHERE Timestamp - Previous > ? \
+            ORDER BY Meter, Previous \
+        ")?;
+        statement.bind((1, min_secs))?;
+
+        statement.into_iter()
+            .map(|row| {
+                let row = row?;
+                Ok(Gap { meter: row.read::<&str, _>(0).to_string(), from: row.read::<i64, _>(1), to: row.read::<i64, _>(2) })
+            })
+            .collect()
+    }
+
+    /// Returns the timestamps of the first and the last reading of each meter with readings.
+    pub fn time_spans(&self) -> Result<Vec<TimeSpan>, anyhow::Error> {
+        let statement = self.0.prepare("SELECT Meter, MIN(Timestamp), MAX(Timestamp) FROM Readings GROUP BY Meter ORDER BY Meter")?;
+
+        statement.into_iter()
+            .map(|row| {
+                let row = row?;
+                Ok(TimeSpan { meter: row.read::<&str, _>(0).to_string(), first: row.read::<i64, _>(1), last: row.read::<i64, _>(2) })
+            })
+            .collect()
+    }
+
+    /// Returns unused pages to the file system, a few at a time so readings keep being recorded.
+    ///
+    /// A `full` vacuum rebuilds the file instead, which locks the database until it is done.
+    /// So do databases created without incremental vacuum, once.
+    pub fn vacuum(&self, full: bool) -> Result<(), anyhow::Error> {
+        const INCREMENTAL: i64 = 2;
+
+        if full || read_pragma(&self.0, "auto_vacuum")? != INCREMENTAL {
+            self.0.execute("PRAGMA auto_vacuum = INCREMENTAL; VACUUM")?;
+            return Ok(());
+        }
+
+        while read_pragma(&self.0, "freelist_count")? > 0 {
+            self.0.execute(format!("PRAGMA incremental_vacuum({PAGES_PER_STEP})"))?;
+            thread::sleep(STEP_PAUSE);
+        }
+
+        Ok(())
+    }
+
+    /// Copies the database to a new file at `path` using SQLite's online backup API,
+    /// so readings can keep being recorded meanwhile.
+    pub fn backup(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if path.exists() {
+            bail!("{} already exists.", path.display());
+        }
+
+        let destination = Connection::open(path)?;
+        copy_database(&self.0, &destination)
+    }
+
+    /// Replaces the contents of the database with the backup at `path`.
+    ///
+    /// The backup is checked for integrity first and migrated to the current schema afterwards.
+    /// Meanwhile the database stays locked, so readings recorded by other processes are skipped.
+    pub fn restore(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if !path.exists() {
+            bail!("{} does not exist.", path.display());
+        }
+
+        let source = Connection::open_with_flags(path, OpenFlags::new().with_read_only())?;
+
+        let problems = integrity_check(&source)?;
+        if problems != ["ok"] {
+            bail!("The backup failed the integrity check: {}", problems.join(", "));
+        }
+
+        // fails if the file isn't a database of this program.
+        source.prepare("SELECT Timestamp, MeterReading FROM Readings LIMIT 1")?;
+
+        copy_database(&source, &self.0)?;
+        self.migrate()
+    }
+}
+
+/// A period without readings of a meter, from the last reading before it to the first reading after it.
+pub struct Gap {
+    pub meter: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+impl Gap {
+    pub fn duration(&self) -> i64 {
+        self.to - self.from
+    }
+}
+
+/// The period from the first to the last reading of a meter.
+pub struct TimeSpan {
+    pub meter: String,
+    pub first: i64,
+    pub last: i64,
+}
+
+fn database_size(connection: &Connection) -> Result<u64, anyhow::Error> {
+    let row = connection.prepare("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()")?
+        .into_iter()
+        .next()
+        .ok_or(anyhow::anyhow!("No size row."))??;
+
+    Ok(row.read::<i64, _>(0) as u64)
+}
+
+fn read_pragma(connection: &Connection, pragma: &str) -> Result<i64, anyhow::Error> {
+    let row = connection.prepare(format!("PRAGMA {pragma}"))?
+        .into_iter()
+        .next()
+        .ok_or(anyhow::anyhow!("No {pragma} row."))??;
+
+    Ok(row.read::<i64, _>(0))
+}
+
+/// Whether `error` is SQLite failing to get a lock held by another connection for longer than `BUSY_TIMEOUT_MS`.
+pub fn is_locked(error: &Error) -> bool {
+    const BUSY: isize = 5;
+    const LOCKED: isize = 6;
+
+    // extended result codes keep the primary code in the lowest byte.
+    error.downcast_ref::<sqlite::Error>()
+        .and_then(|error| error.code)
+        .is_some_and(|code| matches!(code & 0xff, BUSY | LOCKED))
+}
+
+fn integrity_check(connection: &Connection) -> Result<Vec<String>, anyhow::Error> {
+    connection.prepare("PRAGMA integrity_check")?
+        .into_iter()
+        .map(|row| Ok(row?.read::<&str, _>(0).to_string()))
+        .collect()
+}
+
+/// Copies all pages of `source` into `destination` with SQLite's online backup API.
+///
+/// The pages are copied `PAGES_PER_STEP` at a time, releasing the lock on `source` in between so
+/// writers only wait for a single step. A write to `source` restarts the copy, so the result
+/// is still a consistent snapshot. The steps grow with every restart to make sure the copy
+/// finishes even though readings keep being recorded.
+fn copy_database(source: &Connection, destination: &Connection) -> Result<(), anyhow::Error> {
+    const MAX_ATTEMPTS: u32 = 100;
+
+    unsafe {
+        let backup = ffi::sqlite3_backup_init(destination.as_raw(), c"main".as_ptr(), source.as_raw(), c"main".as_ptr());
+        if backup.is_null() {
+            let message = CStr::from_ptr(ffi::sqlite3_errmsg(destination.as_raw()));
+            bail!("Could not start backup: {}", message.to_string_lossy());
+        }
+
+        let mut attempts = 0;
+        let mut pages_per_step = PAGES_PER_STEP;
+        let mut remaining = c_int::MAX;
+        let result = loop {
+            match ffi::sqlite3_backup_step(backup, pages_per_step) {
+                ffi::SQLITE_DONE => break ffi::SQLITE_OK,
+                ffi::SQLITE_OK => {
+                    let now_remaining = ffi::sqlite3_backup_remaining(backup);
+                    // a restarted copy makes no progress.
+                    if now_remaining >= remaining {
+                        pages_per_step = pages_per_step.saturating_mul(2);
+                    }
+                    remaining = now_remaining;
+
+                    thread::sleep(STEP_PAUSE);
+                }
+                ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if attempts < MAX_ATTEMPTS => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                code => break code,
+            }
+        };
+
+        // finishing also reports errors of the last step.
+        let finish_result = ffi::sqlite3_backup_finish(backup);
+        let result = if result != ffi::SQLITE_OK { result } else { finish_result };
+
+        if result != ffi::SQLITE_OK {
+            let message = CStr::from_ptr(ffi::sqlite3_errstr(result));
+            bail!("Backup failed: {}", message.to_string_lossy());
+        }
+    }
+
+    Ok(())
+}
+
+
+fn query_readings<'a>(connection: &'a Connection, query: &ReadingQuery) -> Result<impl Iterator<Item = Result<StoredReading, Error>> + 'a, Error> {
+    let order = match query.order {
+        Order::Asc => "ASC",
+        Order::Desc => "DESC",
+    };
+
+    let mut statement = connection.prepare(format!(" \
+        SELECT Readings.Timestamp, Readings.Meter, Readings.MeterTime, Readings.MeterReading, Readings.LineOne, Readings.LineTwo, Readings.LineThree, \
+            Meters.MeterReadingUnit, Meters.LineOneUnit, Meters.LineTwoUnit, Meters.LineThreeUnit \
+        FROM Readings \
+        LEFT JOIN Meters ON Meters.Name = Readings.Meter \
+        WHERE Readings.Timestamp >= :from AND Readings.Timestamp < :to AND (:meter IS NULL OR Readings.Meter = :meter) \
+        ORDER BY Readings.Timestamp {order} \
+        LIMIT :limit \
+    "))?;
+    statement.bind((":from", query.range.from))?;
+    statement.bind((":to", query.range.to))?;
+    statement.bind((":meter", query.meter.as_deref()))?;
+    // a negative limit means no limit in SQLite.
+    statement.bind((":limit", query.limit.map_or(-1, |limit| limit as i64)))?;
+
+    Ok(statement.into_iter().map(|row| {
+        let row = row?;
+
+        // a value is only given a unit if there is a value.
+        let unit = |value_index: usize, unit_index: usize| -> Result<Option<Unit>, Error> {
+            if let sqlite::Value::Null = row[value_index] {
+                return Ok(None);
+            }
+
+            Ok(row.try_read::<Option<&str>, _>(unit_index)?.and_then(Unit::from_symbol))
+        };
+
+        Ok(StoredReading {
+            timestamp: row.try_read::<i64, _>(0)?,
+            meter: row.try_read::<&str, _>(1)?.to_string(),
+            reading: MeterReading {
+                meter_time: row.try_read::<Option<i64>, _>(2)?.map(|x| x as u32),
+                meter_reading: row.try_read::<Option<f64>, _>(3)?,
+                meter_reading_unit: unit(3, 7)?,
+                line_one: row.try_read::<Option<i64>, _>(4)?.map(|x| x as i32),
+                line_one_unit: unit(4, 8)?,
+                line_two: row.try_read::<Option<i64>, _>(5)?.map(|x| x as i32),
+                line_two_unit: unit(5, 9)?,
+                line_three: row.try_read::<Option<i64>, _>(6)?.map(|x| x as i32),
+                line_three_unit: unit(6, 10)?,
+            },
         })
+    }))
+}
+
+
+fn query_aggregates(connection: &Connection, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+    if bucket_secs <= 0 {
+        bail!("Invalid bucket size {bucket_secs}.");
     }
+
+    let mut statement = connection.prepare(" \
+        SELECT (Timestamp / :bucket) * :bucket AS Bucket, COUNT(*), MAX(MeterReading), AVG(LineOne), AVG(LineTwo), AVG(LineThree) \
+        FROM Readings \
+        WHERE Timestamp >= :from AND Timestamp < :to AND (:meter IS NULL OR Meter = :meter) \
+        GROUP BY Bucket \
+        ORDER BY Bucket \
+    ")?;
+    statement.bind((":bucket", bucket_secs))?;
+    statement.bind((":from", range.from))?;
+    statement.bind((":to", range.to))?;
+    statement.bind((":meter", meter))?;
+
+    statement.into_iter()
+        .map(|row| {
+            let row = row?;
+
+            Ok(Aggregate {
+                timestamp: row.try_read::<i64, _>(0)?,
+                count: row.try_read::<i64, _>(1)? as u64,
+                meter_reading: row.try_read::<Option<f64>, _>(2)?,
+                line_one: row.try_read::<Option<f64>, _>(3)?,
+                line_two: row.try_read::<Option<f64>, _>(4)?,
+                line_three: row.try_read::<Option<f64>, _>(5)?,
+            })
+        })
+        .collect()
 }
 
 
@@ -125,6 +612,36 @@ pub enum Value {
     // U64(u64),
     I64(i64),
     F64(f64),
+    String(String),
+    Binary(Vec<u8>),
+}
+
+impl Value {
+    /// Bytes taken up by the value.
+    fn size(&self) -> usize {
+        match self {
+            Value::I64(_) | Value::F64(_) => 8,
+            Value::String(value) => value.len(),
+            Value::Binary(value) => value.len(),
+        }
+    }
+
+    /// The integer, `None` for any other value.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::I64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The number, `None` for text and blobs.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::I64(value) => Some(*value as f64),
+            Value::F64(value) => Some(*value),
+            _ => None,
+        }
+    }
 }
 
 impl Serialize for Value {
@@ -132,6 +649,9 @@ impl Serialize for Value {
         match self {
             Value::I64(value) => serializer.serialize_i64(*value),
             Value::F64(value) => serializer.serialize_f64(*value),
+            Value::String(value) => serializer.serialize_str(value),
+            // JSON has no bytes, so blobs are encoded as base64 strings.
+            Value::Binary(value) => serializer.serialize_str(&STANDARD.encode(value)),
         }
     }
 }
@@ -142,64 +662,134 @@ pub struct QueryResult {
     took_ms: u64,
     rows_count: u64,
     rows: Vec<Vec<Option<Value>>>,
+    /// Whether there were more than `MAX_QUERY_ROWS` rows, which were cut off.
+    truncated: bool,
 }
 
-pub struct ReadonlyDatabase(ConnectionThreadSafe);
+pub struct ReadonlyDatabase {
+    connection: ConnectionThreadSafe,
+    /// Opened again by `query` for a connection of its own.
+    path: PathBuf,
+}
 
 impl ReadonlyDatabase {
     pub fn load() -> Result<Self, anyhow::Error> {
         let path = Database::path()?;
 
         if path.exists() {
-            let open_flags = OpenFlags::new().with_read_only();
-            let connection = Connection::open_thread_safe_with_flags(&path, open_flags)?;
-
-            Ok(Self(connection))
+            // make sure the schema is up to date, as it can't be migrated through a readonly connection.
+            Database::load()?;
+            Self::open(path)
         } else {
             bail!("Database does not exist.")
         }
     }
 
-    pub fn query(&self, statement: &str) -> Result<QueryResult, anyhow::Error> {
-        let mut statement = self.0.prepare(statement)?;
+    fn open(path: PathBuf) -> Result<Self, anyhow::Error> {
+        let open_flags = OpenFlags::new().with_read_only();
+        let mut connection = Connection::open_thread_safe_with_flags(&path, open_flags)?;
+        connection.set_busy_timeout(BUSY_TIMEOUT_MS)?;
+
+        Ok(Self { connection, path })
+    }
+
+    pub fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<impl Iterator<Item = Result<StoredReading, Error>> + 'a, Error> {
+        query_readings(&self.connection, query)
+    }
+
+    pub fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        query_aggregates(&self.connection, range, meter, bucket_secs)
+    }
+
+    /// The size of the database in bytes.
+    pub fn size(&self) -> Result<u64, anyhow::Error> {
+        database_size(&self.connection)
+    }
+
+    /// Returns the spot prices valid at some point within `range`, ordered by time.
+    pub fn prices(&self, range: &TimeRange) -> Result<Vec<SpotPrice>, Error> {
+        let mut statement = self.connection.prepare(" \
+            SELECT Timestamp, Duration, Price FROM Prices \
+            WHERE Timestamp < :to AND Timestamp + Duration > :from \
+            ORDER BY Timestamp \
+        ")?;
+        statement.bind((":from", range.from))?;
+        statement.bind((":to", range.to))?;
+
+        statement.into_iter()
+            .map(|row| {
+                let row = row?;
+
+                Ok(SpotPrice {
+                    timestamp: row.try_read::<i64, _>(0)?,
+                    duration: row.try_read::<i64, _>(1)?,
+                    price: row.try_read::<f64, _>(2)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Runs a statement from a user, stopping it after `QUERY_TIMEOUT`, once `cancelled` is set
+    /// or after `MAX_QUERY_ROWS` rows or `MAX_QUERY_BYTES`.
+    ///
+    /// Only reading is authorized, which rules out `ATTACH`, setting pragmas and `load_extension`.
+    pub fn query(&self, statement: &str, cancelled: &AtomicBool) -> Result<QueryResult, anyhow::Error> {
+        let interrupt = Interrupt {
+            deadline: Instant::now() + QUERY_TIMEOUT,
+            cancelled,
+        };
+
+        self.query_until(statement, &interrupt)
+    }
+
+    fn query_until(&self, statement: &str, interrupt: &Interrupt) -> Result<QueryResult, anyhow::Error> {
+        // a connection of its own, so the authorizer and progress handler don't affect other requests.
+        let mut connection = Connection::open_with_flags(&self.path, OpenFlags::new().with_read_only())?;
+        connection.set_busy_timeout(BUSY_TIMEOUT_MS)?;
+
+        unsafe {
+            ffi::sqlite3_limit(connection.as_raw(), ffi::SQLITE_LIMIT_LENGTH, MAX_QUERY_BYTES as c_int);
+            ffi::sqlite3_set_authorizer(connection.as_raw(), Some(authorize_query), ptr::null_mut());
+            ffi::sqlite3_progress_handler(connection.as_raw(), PROGRESS_INTERVAL, Some(interrupt_query), interrupt as *const Interrupt as *mut c_void);
+        }
+
+        let mut statement = connection.prepare(statement)?;
 
         let query_start = SystemTime::now();
         let column_names = statement.column_names().to_vec();
         let column_count = statement.column_count();
         
-        // execute statement.
-        statement.next()?;
+        let mut rows = Vec::<Vec<Option<Value>>>::new();
+        let mut bytes = 0;
+        let mut truncated = false;
 
-        // after the statement has been executed, the column types are available.
-        let mut column_types = Vec::<Type>::with_capacity(column_count);
-        for index in 0..column_count {
-            column_types.push(statement.column_type(index)?);
-        }
+        while let State::Row = statement.next().map_err(|error| interrupt.error().unwrap_or(error.into()))? {
+            if rows.len() == MAX_QUERY_ROWS {
+                truncated = true;
+                break;
+            }
 
-        let mut rows = Vec::<Vec<Option<Value>>>::new();
-        for row in statement.into_iter() {
-            let row = row?;
             let mut values = Vec::<Option<Value>>::with_capacity(column_count);
 
+            // the type is checked for every value, since SQLite columns may hold different types per row.
             for index in 0..column_count {
-                let value = match column_types[index] {
-                    Type::Integer => Some(Value::I64(row.read::<i64, usize>(index))),
-                    Type::Float => Some(Value::F64(row.read::<f64, _>(index))),
-                    // Type::Binary => {
-                    //     let bytes = row.read::<&[u8], _>(index);
-                    //     let value = u64::from_le_bytes(bytes.try_into().unwrap());
-                    //     
-                    //     Some(Value::U64(value))
-                    // }
+                let value = match statement.column_type(index)? {
+                    Type::Integer => Some(Value::I64(statement.read::<i64, _>(index)?)),
+                    Type::Float => Some(Value::F64(statement.read::<f64, _>(index)?)),
+                    Type::String => Some(Value::String(statement.read::<String, _>(index)?)),
+                    Type::Binary => Some(Value::Binary(statement.read::<Vec<u8>, _>(index)?)),
                     Type::Null => None,
-                    _ => {
-                        bail!("Unexpected column type \"{:?}\".", column_types[index]);
-                    }
                 };
 
                 values.push(value);
             }
-            
+
+            bytes += values.iter().flatten().map(Value::size).sum::<usize>();
+            if bytes > MAX_QUERY_BYTES {
+                truncated = true;
+                break;
+            }
+
             rows.push(values);
         }
 
@@ -209,22 +799,192 @@ impl ReadonlyDatabase {
             rows_count: rows.len() as u64,
             rows,
             took_ms: duration,
+            truncated,
         };
 
         Ok(query_result)
     }
 }
 
+/// Why `ReadonlyDatabase::query` has to stop, checked by its progress handler.
+struct Interrupt<'a> {
+    deadline: Instant,
+    cancelled: &'a AtomicBool,
+}
+
+impl Interrupt<'_> {
+    /// The reason a statement was interrupted, if it was.
+    fn error(&self) -> Option<anyhow::Error> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Some(anyhow::anyhow!("The query was cancelled."))
+        } else if Instant::now() >= self.deadline {
+            Some(anyhow::anyhow!("The query took longer than {} seconds.", QUERY_TIMEOUT.as_secs()))
+        } else {
+            None
+        }
+    }
+}
+
+extern "C" fn interrupt_query(interrupt: *mut c_void) -> c_int {
+    let interrupt = unsafe { &*(interrupt as *const Interrupt) };
+    interrupt.error().is_some() as c_int
+}
+
+extern "C" fn authorize_query(_: *mut c_void, action: c_int, first: *const c_char, second: *const c_char, _: *const c_char, _: *const c_char) -> c_int {
+    let argument = |argument: *const c_char| (!argument.is_null()).then(|| unsafe { CStr::from_ptr(argument) }.to_string_lossy());
+
+    let authorized = match action {
+        ffi::SQLITE_SELECT | ffi::SQLITE_READ | ffi::SQLITE_RECURSIVE => true,
+        // setting a pragma passes the value as second argument.
+        ffi::SQLITE_PRAGMA => match (argument(first), argument(second)) {
+            (_, None) => true,
+            (Some(pragma), Some(_)) => READONLY_PRAGMAS.iter().any(|readonly| pragma.eq_ignore_ascii_case(readonly)),
+            (None, Some(_)) => false,
+        },
+        ffi::SQLITE_FUNCTION => argument(second).is_some_and(|function| !function.eq_ignore_ascii_case("load_extension")),
+        _ => false,
+    };
+
+    if authorized { ffi::SQLITE_OK } else { ffi::SQLITE_DENY }
+}
 
 pub struct DatabaseMetrics {
     pub location: PathBuf,
     pub count_readings: u64,
     pub file_size: u64,
+    pub latest_reading: Option<StoredReading>,
 }
 
 impl Display for DatabaseMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Location: {}\n", self.location.display())?;
-        write!(f, "Metrics: {} readings, {} bytes", self.count_readings, self.file_size)
+        writeln!(f, "Location: {}", self.location.display())?;
+        write!(f, "Metrics: {} readings, {} bytes", self.count_readings, self.file_size)?;
+
+        if let Some(latest) = &self.latest_reading {
+            write!(f, "\nLatest reading: {} ({}): {}", latest.timestamp, latest.meter, latest.reading.display_compact())?;
+        }
+
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(database: &Database, meter: &str, timestamps: &[i64]) {
+        let reading = MeterReading { meter_reading: Some(0.0), ..Default::default() };
+        for &timestamp in timestamps {
+            database.insert_reading_at(meter, timestamp, &reading).unwrap();
+        }
+    }
+
+    #[test]
+    fn finds_gaps_and_time_spans_per_meter() {
+        let database = Database::in_memory().unwrap();
+        // across both meters, the longest period without readings would be 400 to 1000.
+        insert(&database, "grid", &[0, 100, 400]);
+        insert(&database, "solar", &[200, 1000]);
+
+        let gaps = database.gaps(250).unwrap();
+        let gaps = gaps.iter().map(|gap| (gap.meter.as_str(), gap.from, gap.to)).collect::<Vec<_>>();
+        assert_eq!(gaps, [("grid", 100, 400), ("solar", 200, 1000)]);
+
+        let spans = database.time_spans().unwrap();
+        let spans = spans.iter().map(|span| (span.meter.as_str(), span.first, span.last)).collect::<Vec<_>>();
+        assert_eq!(spans, [("grid", 0, 400), ("solar", 200, 1000)]);
+    }
+
+    /// A database file of its own, since `ReadonlyDatabase::query` opens another connection to it.
+    fn readonly_database(name: &str) -> ReadonlyDatabase {
+        let path = std::env::temp_dir().join(format!("database-{}-{name}.sqlite3", std::process::id()));
+        let _ = fs::remove_file(&path);
+        Database::init(&path).unwrap().migrate().unwrap();
+
+        ReadonlyDatabase::open(path).unwrap()
+    }
+
+    fn query(database: &ReadonlyDatabase, statement: &str) -> Result<QueryResult, Error> {
+        database.query(statement, &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn only_authorizes_reading_queries() {
+        let database = readonly_database("authorize");
+
+        assert!(query(&database, "SELECT count(*) FROM Readings").is_ok());
+        assert!(query(&database, "PRAGMA table_info(Readings)").is_ok());
+        assert!(query(&database, "PRAGMA user_version").is_ok());
+
+        for statement in [
+            "ATTACH DATABASE ':memory:' AS other",
+            "SELECT load_extension('evil')",
+            "PRAGMA user_version = 100",
+            "PRAGMA journal_mode = DELETE",
+            "DELETE FROM Readings",
+        ] {
+            let error = query(&database, statement).err().unwrap();
+            assert!(error.to_string().contains("not authorized"), "{statement}: {error}");
+        }
+
+        fs::remove_file(&database.path).unwrap();
+    }
+
+    #[test]
+    fn limits_the_bytes_of_a_query() {
+        let database = readonly_database("bytes");
+
+        assert!(query(&database, &format!("SELECT zeroblob({})", MAX_QUERY_BYTES + 1)).is_err());
+        assert!(query(&database, &format!("SELECT length(randomblob({}))", MAX_QUERY_BYTES + 1)).is_err());
+
+        let megabyte_rows = "WITH RECURSIVE counter(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM counter LIMIT 100) \
+            SELECT zeroblob(1024 * 1024) FROM counter";
+        let result = query(&database, megabyte_rows).unwrap();
+        assert_eq!((result.rows_count, result.truncated), (16, true));
+
+        fs::remove_file(&database.path).unwrap();
+    }
+
+    #[test]
+    fn stops_a_query_once_timed_out_or_cancelled() {
+        let database = readonly_database("interrupt");
+        let endless = "WITH RECURSIVE counter(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM counter) SELECT count(*) FROM counter";
+
+        let running = AtomicBool::new(false);
+        let interrupt = Interrupt { deadline: Instant::now() + Duration::from_millis(100), cancelled: &running };
+        let error = database.query_until(endless, &interrupt).err().unwrap();
+        assert_eq!(error.to_string(), format!("The query took longer than {} seconds.", QUERY_TIMEOUT.as_secs()));
+
+        let cancelled = AtomicBool::new(false);
+        let error = thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(100));
+                cancelled.store(true, Ordering::Relaxed);
+            });
+
+            database.query(endless, &cancelled).err().unwrap()
+        });
+        assert_eq!(error.to_string(), "The query was cancelled.");
+
+        fs::remove_file(&database.path).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn types_the_values_of_a_column_per_row() {
+        let database = readonly_database("types");
+
+        let result = query(&database, "SELECT 42 UNION ALL SELECT 'text' UNION ALL SELECT x'0102' UNION ALL SELECT 1.5 UNION ALL SELECT NULL").unwrap();
+        let values = result.rows.into_iter().map(|mut row| row.remove(0)).collect::<Vec<_>>();
+
+        assert!(matches!(values[0], Some(Value::I64(42))));
+        assert!(matches!(&values[1], Some(Value::String(value)) if value == "text"));
+        assert!(matches!(&values[2], Some(Value::Binary(value)) if value == &[1, 2]));
+        assert!(matches!(values[3], Some(Value::F64(value)) if value == 1.5));
+        assert!(values[4].is_none());
+
+        let values = values.iter().flatten();
+        assert_eq!(values.clone().map(Value::as_i64).collect::<Vec<_>>(), [Some(42), None, None, None]);
+        assert_eq!(values.map(Value::as_f64).collect::<Vec<_>>(), [Some(42.0), None, None, Some(1.5)]);
+
+        fs::remove_file(&database.path).unwrap();
+    }
+}
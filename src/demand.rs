@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, AlertState, Metric, Notifier};
+use crate::storage::{ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::{format_timestamp, Interval, TimeRange};
+
+/// The rule name of the alerts about the projected demand.
+const ALERT_RULE: &str = "Demand peak";
+
+/// Readings further apart than this don't tell the power in between, so no energy is counted for the gap.
+const MAX_GAP_SECS: i64 = 5 * 60;
+
+/// The `[demand]` section of the config.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DemandConfig {
+    /// Length of a demand window in seconds.
+    pub window: i64,
+    /// Whether the window slides along with every reading instead of following fixed clock intervals.
+    pub sliding: bool,
+    /// Fraction of the monthly peak at which the projected demand of the current window raises an alert.
+    pub alert_threshold: f64,
+}
+
+impl Default for DemandConfig {
+    fn default() -> Self {
+        DemandConfig {
+            window: 15 * 60,
+            sliding: false,
+            alert_threshold: 0.9,
+        }
+    }
+}
+
+/// The average power drawn from the grid within a window, in W.
+#[derive(Clone, Copy, Serialize)]
+pub struct Demand {
+    pub from: i64,
+    pub to: i64,
+    pub demand: f64,
+}
+
+#[derive(Serialize)]
+pub struct MonthlyPeak {
+    pub from: i64,
+    pub to: i64,
+    pub peak: Demand,
+}
+
+#[derive(Serialize)]
+pub struct DemandReport {
+    pub meter: String,
+    pub window: i64,
+    pub sliding: bool,
+    pub peaks: Vec<MonthlyPeak>,
+}
+
+/// Computes the demand of consecutive windows from power readings.
+///
+/// The power of a reading is held until the next one. Only power drawn from the grid counts,
+/// readings with a negative total power (feeding in) contribute nothing.
+pub struct DemandWindows {
+    length: i64,
+    sliding: bool,
+    previous: Option<(i64, f64)>,
+    /// Fixed windows: start of the current window, the energy (Ws) within it so far
+    /// and whether there were readings since its start.
+    current: Option<(i64, f64, bool)>,
+    /// Sliding windows: the readings within the last window with the energy (Ws) accumulated up to them.
+    points: VecDeque<(i64, f64)>,
+    accumulated: f64,
+}
+
+impl DemandWindows {
+    pub fn new(config: &DemandConfig) -> Result<Self, Error> {
+        if config.window <= 0 {
+            bail!("The demand window has to be at least a second.");
+        }
+
+        Ok(DemandWindows {
+            length: config.window,
+            sliding: config.sliding,
+            previous: None,
+            current: None,
+            points: VecDeque::new(),
+            accumulated: 0.0,
+        })
+    }
+
+    /// Adds a reading of the total power and returns the windows completed by it.
+    ///
+    /// Sliding windows complete with every reading once a full window has been seen.
+    pub fn push(&mut self, timestamp: i64, power: f64) -> Vec<Demand> {
+        let power = power.max(0.0);
+        let previous = self.previous.replace((timestamp, power));
+
+        if self.sliding {
+            return self.push_sliding(timestamp, previous).into_iter().collect();
+        }
+
+        let mut completed = Vec::new();
+        let gap = previous.is_none_or(|(previous_timestamp, _)| timestamp - previous_timestamp > MAX_GAP_SECS);
+
+        // a gap ends the window, as its demand is unknown.
+        if gap {
+            self.current = Some((self.window_start(timestamp), 0.0, timestamp == self.window_start(timestamp)));
+            return completed;
+        }
+
+        let (mut from, previous_power) = previous.unwrap();
+        while let Some((start, energy, complete)) = self.current.as_mut() {
+            let end = *start + self.length;
+            *energy += previous_power * (timestamp.min(end) - from) as f64;
+
+            if timestamp < end {
+                break;
+            }
+
+            if *complete {
+                completed.push(Demand { from: *start, to: end, demand: *energy / self.length as f64 });
+            }
+
+            self.current = Some((end, 0.0, true));
+            from = end;
+        }
+
+        completed
+    }
+
+    fn push_sliding(&mut self, timestamp: i64, previous: Option<(i64, f64)>) -> Option<Demand> {
+        match previous {
+            Some((previous_timestamp, previous_power)) if timestamp - previous_timestamp <= MAX_GAP_SECS => {
+                self.accumulated += previous_power * (timestamp - previous_timestamp) as f64;
+            }
+            _ => self.points.clear(),
+        }
+
+        self.points.push_back((timestamp, self.accumulated));
+
+        let start = timestamp - self.length;
+        while self.points.len() > 1 && self.points[1].0 <= start {
+            self.points.pop_front();
+        }
+
+        let &(first_timestamp, first_accumulated) = self.points.front()?;
+        if first_timestamp > start {
+            return None;
+        }
+
+        // the power of the first point was held until the second one.
+        let (second_timestamp, second_accumulated) = self.points[1];
+        let first_power = (second_accumulated - first_accumulated) / (second_timestamp - first_timestamp) as f64;
+        let energy = self.accumulated - first_accumulated - first_power * (start - first_timestamp) as f64;
+
+        Some(Demand { from: start, to: timestamp, demand: energy / self.length as f64 })
+    }
+
+    /// Projects the demand of the window containing `now`, assuming the latest power is held until its end.
+    ///
+    /// For sliding windows this is the demand of the window ending now.
+    pub fn projected(&self, now: i64) -> Option<Demand> {
+        let (timestamp, power) = self.previous?;
+        if now - timestamp > MAX_GAP_SECS {
+            return None;
+        }
+
+        if self.sliding {
+            let start = now - self.length;
+            let (first_timestamp, first_accumulated) = *self.points.front()?;
+            let energy = self.accumulated + power * (now - timestamp) as f64 - first_accumulated;
+
+            // the part of the window before the first reading is assumed to be at the same power.
+            let missing = (first_timestamp - start).max(0) as f64;
+            let demand = energy / (self.length as f64 - missing).max(1.0);
+
+            return Some(Demand { from: start, to: now, demand });
+        }
+
+        let (start, energy, _) = self.current?;
+        let end = start + self.length;
+        let energy = energy + power * (end - timestamp) as f64;
+
+        Some(Demand { from: start, to: end, demand: energy / self.length as f64 })
+    }
+
+    /// Fixed windows follow the clock, e.g. :00, :15, :30 and :45 for 15 minutes.
+    fn window_start(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.length)
+    }
+}
+
+/// Finds the highest demand of each month within `range`.
+pub fn monthly_peaks(store: &dyn ReadingStore, range: &TimeRange, meter: &str, config: &DemandConfig) -> Result<DemandReport, Error> {
+    let query = ReadingQuery {
+        meter: Some(meter.to_string()),
+        ..ReadingQuery::new(*range)
+    };
+
+    let mut windows = DemandWindows::new(config)?;
+    let mut peaks = Vec::<MonthlyPeak>::new();
+
+    for reading in store.readings(&query)? {
+        let StoredReading { timestamp, reading, .. } = reading?;
+        let Some(power) = reading.total_power() else {
+            continue;
+        };
+
+        for demand in windows.push(timestamp, power as f64) {
+            let month = Interval::Month.start_of(demand.to - 1)?;
+
+            match peaks.last_mut() {
+                Some(peak) if peak.from == month => {
+                    if demand.demand > peak.peak.demand {
+                        peak.peak = demand;
+                    }
+                }
+                _ => peaks.push(MonthlyPeak { from: month, to: Interval::Month.next(month)?, peak: demand }),
+            }
+        }
+    }
+
+    Ok(DemandReport {
+        meter: meter.to_string(),
+        window: config.window,
+        sliding: config.sliding,
+        peaks,
+    })
+}
+
+/// The state of the current demand window, as shown by `/api/demand/now`.
+#[derive(Clone, Default, Serialize)]
+pub struct LiveDemand {
+    pub projected: Option<Demand>,
+    pub monthly_peak: Option<Demand>,
+    /// Whether the projected demand reaches `alert_threshold` of the monthly peak.
+    pub alert: bool,
+}
+
+/// Follows the readings as they are recorded to keep the projected demand and the monthly peak up to date.
+///
+/// Once the projected demand reaches `alert_threshold` of the monthly peak, an alert is sent like those of the
+/// alert rules, once per window. It resolves when the projected demand drops below the threshold again.
+/// New monthly peaks are only printed.
+pub struct DemandTracker {
+    meter: String,
+    config: DemandConfig,
+    windows: DemandWindows,
+    monthly_peak: Option<Demand>,
+    state: Arc<Mutex<LiveDemand>>,
+    notifier: Notifier,
+}
+
+impl DemandTracker {
+    /// Creates a tracker, taking the peak of the current month so far from the stored readings.
+    pub fn new(store: &dyn ReadingStore, meter: &str, config: DemandConfig, notifier: Notifier, now: i64) -> Result<Self, Error> {
+        let month = Interval::Month.start_of(now)?;
+        let report = monthly_peaks(store, &TimeRange::new(Some(month), None)?, meter, &config)?;
+
+        Ok(DemandTracker {
+            meter: meter.to_string(),
+            config,
+            windows: DemandWindows::new(&config)?,
+            monthly_peak: report.peaks.into_iter().find(|peak| peak.from == month).map(|peak| peak.peak),
+            state: Default::default(),
+            notifier,
+        })
+    }
+
+    pub fn state(&self) -> Arc<Mutex<LiveDemand>> {
+        self.state.clone()
+    }
+
+    /// Processes readings until the sender is dropped.
+    pub fn enter(mut self, readings: Receiver<StoredReading>) {
+        let mut alerted_window = None;
+
+        for StoredReading { timestamp, reading, .. } in readings {
+            let Some(power) = reading.total_power() else {
+                continue;
+            };
+
+            for demand in self.windows.push(timestamp, power as f64) {
+                match self.monthly_peak {
+                    Some(peak) if month_of(&peak) == month_of(&demand) => {
+                        if demand.demand > peak.demand {
+                            // sliding windows would announce every step of a rising demand.
+                            if !self.config.sliding {
+                                println!("New monthly demand peak: {:.0} W from {}.", demand.demand, format_timestamp(demand.from));
+                            }
+
+                            self.monthly_peak = Some(demand);
+                        }
+                    }
+                    _ => self.monthly_peak = Some(demand),
+                }
+            }
+
+            let projected = self.windows.projected(timestamp);
+            let alert = match (projected, self.monthly_peak) {
+                (Some(projected), Some(peak)) => projected.demand >= peak.demand * self.config.alert_threshold,
+                _ => false,
+            };
+
+            // alert once per window, rather than with every reading.
+            match projected {
+                Some(projected) if alert => {
+                    let window = if self.config.sliding { None } else { Some(projected.from) };
+                    if alerted_window != Some(window) {
+                        self.notifier.send(self.alert(AlertState::Triggered, &projected, timestamp), None);
+                        alerted_window = Some(window);
+                    }
+                }
+                Some(projected) if alerted_window.is_some() => {
+                    self.notifier.send(self.alert(AlertState::Resolved, &projected, timestamp), None);
+                    alerted_window = None;
+                }
+                _ => {}
+            }
+
+            *self.state.lock().unwrap() = LiveDemand {
+                projected,
+                monthly_peak: self.monthly_peak,
+                alert,
+            };
+        }
+    }
+
+    fn alert(&self, state: AlertState, projected: &Demand, now: i64) -> Alert {
+        let peak = self.monthly_peak.map_or(0.0, |peak| peak.demand);
+        let value = projected.demand;
+
+        let message = match state {
+            AlertState::Triggered => format!("{ALERT_RULE}: The projected demand of {} is {value:.0} W, close to this month's peak of {peak:.0} W.", self.meter),
+            AlertState::Resolved => format!("{ALERT_RULE}: The projected demand of {} is back to {value:.0} W.", self.meter),
+        };
+
+        Alert {
+            timestamp: now,
+            meter: self.meter.clone(),
+            rule: ALERT_RULE.to_string(),
+            state,
+            metric: Metric::Demand,
+            value,
+            threshold: peak * self.config.alert_threshold,
+            message,
+        }
+    }
+}
+
+/// A window belongs to the month it ends in, like a utility would bill it.
+fn month_of(demand: &Demand) -> Option<i64> {
+    Interval::Month.start_of(demand.to - 1).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::alerts::AlertConfig;
+    use crate::meter_reading::MeterReading;
+    use crate::storage::MemoryStore;
+    use crate::test_server;
+    use crate::timestamp::{local_timestamp, use_test_time_zone};
+
+    fn reading(timestamp: i64, power: i32) -> StoredReading {
+        StoredReading {
+            timestamp,
+            meter: "default".to_string(),
+            reading: MeterReading { line_one: Some(power), ..Default::default() },
+        }
+    }
+
+    #[test]
+    fn notifies_when_the_projected_demand_nears_the_monthly_peak() {
+        use_test_time_zone();
+        let month = local_timestamp(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_time(Default::default())).unwrap();
+
+        // two windows of 1000 W make up the peak so far.
+        let store = MemoryStore::default();
+        for timestamp in (month + 3600..=month + 5400).step_by(60) {
+            store.insert("default", timestamp, &reading(timestamp, 1000).reading).unwrap();
+        }
+
+        let (url, requests) = test_server::serve(200);
+        let config = AlertConfig { webhook: Some(format!("{url}/hook")), rules: Vec::new() };
+        let notifier = Notifier::start(&config, Box::new(MemoryStore::default()));
+        let start = month + 86_400;
+        let tracker = DemandTracker::new(&store, "default", DemandConfig::default(), notifier, start).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send(reading(start, 950)).unwrap();
+        sender.send(reading(start + 60, 950)).unwrap();
+        sender.send(reading(start + 120, 100)).unwrap();
+        drop(sender);
+        tracker.enter(receiver);
+
+        let alerts = (0..2)
+            .map(|_| serde_json::from_str::<serde_json::Value>(&requests.recv_timeout(Duration::from_secs(5)).unwrap().body).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(alerts[0], serde_json::json!({
+            "timestamp": start,
+            "meter": "default",
+            "rule": "Demand peak",
+            "state": "triggered",
+            "metric": "demand",
+            "value": 950.0,
+            "threshold": 900.0,
+            "message": "Demand peak: The projected demand of default is 950 W, close to this month's peak of 1000 W.",
+        }));
+        assert_eq!(alerts[1]["state"], "resolved");
+        assert_eq!(alerts[1]["message"], "Demand peak: The projected demand of default is back to 213 W.");
+        assert!(requests.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
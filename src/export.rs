@@ -0,0 +1,283 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap_derive::ValueEnum;
+use parquet::basic::Compression;
+use parquet::data_type::{DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize};
+use sqlite::Type;
+
+use crate::database::Value;
+use crate::storage::{Aggregate, ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::TimeRange;
+
+const PARQUET_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Names and types of the columns of a row.
+type Columns = &'static [(&'static str, Type)];
+
+/// The values of a single row, `None` being a missing value.
+type ValueRow = Vec<Option<Value>>;
+
+/// Columns of an export without a rollup.
+const READING_COLUMNS: Columns = &[
+    ("Timestamp", Type::Integer),
+    ("MeterTime", Type::Integer),
+    ("MeterReading", Type::Float),
+    ("LineOne", Type::Integer),
+    ("LineTwo", Type::Integer),
+    ("LineThree", Type::Integer),
+];
+
+/// Columns of an export with a rollup, see `Aggregate`.
+const AGGREGATE_COLUMNS: Columns = &[
+    ("Timestamp", Type::Integer),
+    ("Count", Type::Integer),
+    ("MeterReading", Type::Float),
+    ("LineOne", Type::Float),
+    ("LineTwo", Type::Float),
+    ("LineThree", Type::Float),
+];
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Aggregates readings into fixed buckets (aligned to UTC) instead of exporting every row.
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rollup {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Rollup {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Rollup::Minute => 60,
+            Rollup::Hour => 60 * 60,
+            Rollup::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Writes the readings (or rollups) within `range` to `writer` and returns the number of rows written.
+pub fn export(
+    store: &dyn ReadingStore,
+    range: &TimeRange,
+    meter: Option<&str>,
+    rollup: Option<Rollup>,
+    format: ExportFormat,
+    mut writer: impl Write + Send,
+) -> Result<u64, Error> {
+    let (columns, rows): (Columns, Box<dyn Iterator<Item = Result<ValueRow, Error>>>) = match rollup {
+        None => {
+            let query = ReadingQuery {
+                meter: meter.map(str::to_string),
+                ..ReadingQuery::new(*range)
+            };
+
+            let rows = store.readings(&query)?.map(|reading| reading.map(|reading| reading_row(&reading)));
+            (READING_COLUMNS, Box::new(rows))
+        }
+        Some(rollup) => {
+            let rows = store.aggregates(range, meter, rollup.seconds())?.into_iter().map(|aggregate| Ok(aggregate_row(&aggregate)));
+            (AGGREGATE_COLUMNS, Box::new(rows))
+        }
+    };
+
+    let count = match format {
+        ExportFormat::Csv => write_csv(columns, rows, &mut writer)?,
+        ExportFormat::Jsonl => write_jsonl(columns, rows, &mut writer)?,
+        ExportFormat::Parquet => write_parquet(columns, rows, &mut writer)?,
+    };
+
+    writer.flush()?;
+    Ok(count)
+}
+
+fn reading_row(reading: &StoredReading) -> ValueRow {
+    vec![
+        Some(Value::I64(reading.timestamp)),
+        reading.reading.meter_time.map(|value| Value::I64(value as i64)),
+        reading.reading.meter_reading.map(Value::F64),
+        reading.reading.line_one.map(|value| Value::I64(value as i64)),
+        reading.reading.line_two.map(|value| Value::I64(value as i64)),
+        reading.reading.line_three.map(|value| Value::I64(value as i64)),
+    ]
+}
+
+fn aggregate_row(aggregate: &Aggregate) -> ValueRow {
+    vec![
+        Some(Value::I64(aggregate.timestamp)),
+        Some(Value::I64(aggregate.count as i64)),
+        aggregate.meter_reading.map(Value::F64),
+        aggregate.line_one.map(Value::F64),
+        aggregate.line_two.map(Value::F64),
+        aggregate.line_three.map(Value::F64),
+    ]
+}
+
+fn write_csv(
+    columns: &[(&str, Type)],
+    rows: impl Iterator<Item = Result<ValueRow, Error>>,
+    writer: impl Write,
+) -> Result<u64, Error> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(columns.iter().map(|(name, _)| name))?;
+
+    let mut count = 0;
+    for row in rows {
+        let values = row?;
+        let record = values.iter().map(|value| match value {
+            Some(Value::I64(value)) => value.to_string(),
+            Some(Value::F64(value)) => value.to_string(),
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Binary(value)) => STANDARD.encode(value),
+            None => String::new(),
+        });
+
+        csv_writer.write_record(record)?;
+        count += 1;
+    }
+
+    csv_writer.flush()?;
+    Ok(count)
+}
+
+/// A row serialized as a JSON object with the keys in column order.
+struct JsonRow<'a> {
+    columns: &'a [(&'a str, Type)],
+    values: &'a [Option<Value>],
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for ((name, _), value) in self.columns.iter().zip(self.values) {
+            map.serialize_entry(name, value)?;
+        }
+
+        map.end()
+    }
+}
+
+fn write_jsonl(
+    columns: &[(&str, Type)],
+    rows: impl Iterator<Item = Result<ValueRow, Error>>,
+    mut writer: impl Write,
+) -> Result<u64, Error> {
+    let mut count = 0;
+    for row in rows {
+        let values = row?;
+
+        serde_json::to_writer(&mut writer, &JsonRow { columns, values: &values })?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn write_parquet(
+    columns: &[(&str, Type)],
+    rows: impl Iterator<Item = Result<ValueRow, Error>>,
+    writer: impl Write + Send,
+) -> Result<u64, Error> {
+    let fields = columns.iter()
+        .map(|(name, column_type)| match column_type {
+            Type::Integer => format!("OPTIONAL INT64 {name};"),
+            _ => format!("OPTIONAL DOUBLE {name};"),
+        })
+        .collect::<String>();
+
+    let schema = Arc::new(parse_message_type(&format!("message readings {{ {fields} }}"))?);
+    let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+    let mut file_writer = SerializedFileWriter::new(writer, schema, properties)?;
+
+    let mut count = 0;
+    let mut row_group = Vec::with_capacity(PARQUET_ROW_GROUP_SIZE);
+    for row in rows {
+        row_group.push(row?);
+
+        if row_group.len() == PARQUET_ROW_GROUP_SIZE {
+            write_parquet_row_group(&mut file_writer, columns, &row_group)?;
+            count += row_group.len() as u64;
+            row_group.clear();
+        }
+    }
+
+    if !row_group.is_empty() {
+        write_parquet_row_group(&mut file_writer, columns, &row_group)?;
+        count += row_group.len() as u64;
+    }
+
+    file_writer.close()?;
+    Ok(count)
+}
+
+fn write_parquet_row_group<W: Write + Send>(
+    file_writer: &mut SerializedFileWriter<W>,
+    columns: &[(&str, Type)],
+    rows: &[ValueRow],
+) -> Result<(), Error> {
+    let mut row_group_writer = file_writer.next_row_group()?;
+
+    for (index, (name, column_type)) in columns.iter().enumerate() {
+        let mut column_writer = row_group_writer.next_column()?
+            .ok_or_else(|| anyhow!("Missing parquet column \"{name}\"."))?;
+
+        // a definition level of 0 marks a null value, 1 a present one. Values not of the column's type are null.
+        let values = rows.iter().map(|row| row[index].as_ref());
+
+        match column_type {
+            Type::Integer => {
+                let values = values.map(|value| value.and_then(Value::as_i64)).collect::<Vec<_>>();
+                let definition_levels = values.iter().map(|value| value.is_some() as i16).collect::<Vec<_>>();
+                let values = values.into_iter().flatten().collect::<Vec<_>>();
+                column_writer.typed::<Int64Type>().write_batch(&values, Some(&definition_levels), None)?;
+            }
+            _ => {
+                let values = values.map(|value| value.and_then(Value::as_f64)).collect::<Vec<_>>();
+                let definition_levels = values.iter().map(|value| value.is_some() as i16).collect::<Vec<_>>();
+                let values = values.into_iter().flatten().collect::<Vec<_>>();
+                column_writer.typed::<DoubleType>().write_batch(&values, Some(&definition_levels), None)?;
+            }
+        }
+
+        column_writer.close()?;
+    }
+
+    row_group_writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use anyhow::{bail, Error};
+use serde::Serialize;
+
+use crate::storage::{ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::{Interval, TimeRange};
+
+/// Readings further apart than this don't tell the power in between, so the gap isn't counted.
+const MAX_GAP_SECS: i64 = 5 * 60;
+
+/// The largest difference between the phases grid operators usually allow, e.g. in Germany.
+pub const DEFAULT_THRESHOLD: f64 = 4600.0;
+
+/// The energy drawn through one line. Feeding in doesn't count.
+#[derive(Default, Serialize)]
+pub struct PhaseShare {
+    /// In Wh.
+    pub energy: f64,
+    /// Fraction of the energy of all lines, `None` if no energy was drawn at all.
+    pub share: Option<f64>,
+    pub max_power: Option<i64>,
+}
+
+/// How evenly the power was spread over the lines within a bucket. Powers are in W.
+#[derive(Default, Serialize)]
+pub struct Imbalance {
+    pub from: i64,
+    pub to: i64,
+    pub line_one: PhaseShare,
+    pub line_two: PhaseShare,
+    pub line_three: PhaseShare,
+    /// The deviation of the line furthest from the mean of the three, averaged over the readings.
+    pub mean_deviation: Option<f64>,
+    pub max_deviation: Option<f64>,
+    /// The largest difference between the most and the least loaded line.
+    pub max_asymmetry: Option<i64>,
+    /// Seconds during which the asymmetry exceeded the threshold.
+    pub above_threshold: i64,
+    /// Seconds covered by readings, which `above_threshold` is a part of.
+    pub covered: i64,
+    pub samples: u64,
+}
+
+#[derive(Serialize)]
+pub struct ImbalanceReport {
+    pub meter: String,
+    pub bucket: Option<Interval>,
+    pub threshold: f64,
+    pub buckets: Vec<Imbalance>,
+}
+
+impl Imbalance {
+    fn add_reading(&mut self, lines: [i64; 3]) {
+        let mean = lines.iter().sum::<i64>() as f64 / 3.0;
+        let deviation = lines.iter().map(|&line| (line as f64 - mean).abs()).fold(0.0, f64::max);
+        let asymmetry = lines.iter().max().unwrap() - lines.iter().min().unwrap();
+
+        self.mean_deviation = Some(self.mean_deviation.unwrap_or(0.0) + deviation);
+        self.max_deviation = Some(self.max_deviation.map_or(deviation, |max| max.max(deviation)));
+        self.max_asymmetry = Some(self.max_asymmetry.map_or(asymmetry, |max| max.max(asymmetry)));
+        self.samples += 1;
+
+        for (share, line) in self.shares_mut().into_iter().zip(lines) {
+            share.max_power = Some(share.max_power.map_or(line, |max| max.max(line)));
+        }
+    }
+
+    /// Counts the power of a reading, held for `duration` seconds until the next one.
+    fn add_duration(&mut self, lines: [i64; 3], duration: i64, threshold: f64) {
+        for (share, line) in self.shares_mut().into_iter().zip(lines) {
+            share.energy += line.max(0) as f64 * duration as f64 / 3600.0;
+        }
+
+        let asymmetry = lines.iter().max().unwrap() - lines.iter().min().unwrap();
+        if asymmetry as f64 > threshold {
+            self.above_threshold += duration;
+        }
+
+        self.covered += duration;
+    }
+
+    fn finish(mut self, from: i64, to: i64) -> Self {
+        let total = self.line_one.energy + self.line_two.energy + self.line_three.energy;
+        for share in self.shares_mut() {
+            share.share = (total > 0.0).then(|| share.energy / total);
+        }
+
+        self.mean_deviation = self.mean_deviation.map(|sum| sum / self.samples as f64);
+        Imbalance { from, to, ..self }
+    }
+
+    fn shares_mut(&mut self) -> [&mut PhaseShare; 3] {
+        [&mut self.line_one, &mut self.line_two, &mut self.line_three]
+    }
+}
+
+/// Analyses how the power is spread over the three lines, per `bucket` or over the whole range.
+/// Without a bucket, `from` and `to` of the result span the readings found.
+///
+/// Only readings with the power of all three lines are taken into account. The asymmetry is the
+/// difference between the most and the least loaded line, as limited by grid operators.
+pub fn imbalance(
+    store: &dyn ReadingStore,
+    range: &TimeRange,
+    meter: &str,
+    bucket: Option<Interval>,
+    threshold: f64,
+) -> Result<ImbalanceReport, Error> {
+    if threshold < 0.0 {
+        bail!("The threshold can't be negative.");
+    }
+
+    let query = ReadingQuery {
+        meter: Some(meter.to_string()),
+        ..ReadingQuery::new(*range)
+    };
+
+    let mut buckets = Vec::new();
+    let mut current: Option<(i64, i64, Imbalance)> = None;
+    let mut previous: Option<(i64, [i64; 3])> = None;
+    let mut last_timestamp = 0;
+
+    for reading in store.readings(&query)? {
+        let StoredReading { timestamp, reading, .. } = reading?;
+        let (Some(line_one), Some(line_two), Some(line_three)) = (reading.line_one, reading.line_two, reading.line_three) else {
+            continue;
+        };
+        let lines = [line_one as i64, line_two as i64, line_three as i64];
+
+        // the previous reading lasted until this one, which counts towards its bucket.
+        if let (Some((previous_timestamp, previous_lines)), Some((_, _, imbalance))) = (previous, current.as_mut()) {
+            let duration = timestamp - previous_timestamp;
+            if duration <= MAX_GAP_SECS {
+                imbalance.add_duration(previous_lines, duration, threshold);
+            }
+        }
+        previous = Some((timestamp, lines));
+
+        if current.as_ref().is_some_and(|(_, end, _)| timestamp >= *end) {
+            let (start, end, imbalance) = current.take().unwrap();
+            buckets.push(imbalance.finish(start.max(range.from), end.min(range.to)));
+        }
+
+        let (_, _, imbalance) = match &mut current {
+            Some(current) => current,
+            None => {
+                let (start, end) = match bucket {
+                    Some(bucket) => {
+                        let start = bucket.start_of(timestamp)?;
+                        (start, bucket.next(start)?)
+                    }
+                    // the whole range, which is narrowed down to the readings below.
+                    None => (timestamp, i64::MAX),
+                };
+
+                current.insert((start, end, Imbalance::default()))
+            }
+        };
+
+        imbalance.add_reading(lines);
+        last_timestamp = timestamp;
+    }
+
+    if let Some((start, end, imbalance)) = current {
+        let end = if bucket.is_some() { end.min(range.to) } else { last_timestamp + 1 };
+        buckets.push(imbalance.finish(start.max(range.from), end));
+    }
+
+    Ok(ImbalanceReport {
+        meter: meter.to_string(),
+        bucket,
+        threshold,
+        buckets,
+    })
+}
@@ -0,0 +1,191 @@
+mod portal;
+mod prices;
+mod tasmota;
+mod vzlogger;
+
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+use anyhow::{anyhow, Error};
+use chrono::NaiveDateTime;
+use clap_derive::ValueEnum;
+use csv::StringRecord;
+
+use crate::database::Database;
+use crate::meter_reading::MeterReading;
+use crate::timestamp::{local_timestamp, parse_timestamp};
+
+pub use prices::{read_prices, PriceFormat, PriceUnit};
+
+/// How many rejected rows are reported individually before only counting them.
+const MAX_REPORTED_REJECTIONS: u64 = 10;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImportFormat {
+    /// vzlogger / volkszaehler channel export: `<timestamp in ms>;<meter reading in Wh>`
+    Vzlogger,
+    /// Tasmota SML script CSV log with a header row, e.g. `Time,Total_in,Power_L1,Power_L2,Power_L3`
+    Tasmota,
+    /// Utility portal export with the consumption in kWh per 15-minute interval
+    Portal,
+}
+
+/// A row of an external file, mapped onto the columns of `Readings`.
+pub struct ImportRecord {
+    pub timestamp: i64,
+    pub reading: MeterReading,
+}
+
+pub struct ImportOptions {
+    /// The meter reading in Wh at the start of a file which only contains consumption values.
+    pub initial_reading: f64,
+}
+
+#[derive(Default)]
+pub struct ImportSummary {
+    pub inserted: u64,
+    pub skipped: u64,
+    pub rejected: u64,
+}
+
+impl Display for ImportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} inserted, {} skipped (duplicate timestamp), {} rejected", self.inserted, self.skipped, self.rejected)
+    }
+}
+
+/// A row which leaves the rows after it unusable, so the whole import fails instead of just rejecting the row.
+#[derive(Debug)]
+pub struct ImportAborted(pub String);
+
+impl Display for ImportAborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} Nothing was imported.", self.0)
+    }
+}
+
+impl std::error::Error for ImportAborted {}
+
+/// Maps every row of `reader` onto a record. Rows which can't be mapped yield an error naming their line.
+pub fn read_records(
+    format: ImportFormat,
+    reader: impl Read + 'static,
+    options: &ImportOptions,
+) -> Result<Box<dyn Iterator<Item = Result<ImportRecord, Error>>>, Error> {
+    match format {
+        ImportFormat::Vzlogger => vzlogger::records(reader),
+        ImportFormat::Tasmota => tasmota::records(reader),
+        ImportFormat::Portal => portal::records(reader, options),
+    }
+}
+
+/// Inserts the records within a single transaction, which is rolled back if a record is `ImportAborted`.
+///
+/// Records whose timestamp already exists for the meter are skipped by the unique `idx_meter_timestamp` index.
+pub fn import(database: &Database, meter: &str, records: impl Iterator<Item = Result<ImportRecord, Error>>) -> Result<ImportSummary, Error> {
+    database.transaction(|database| {
+        let mut summary = ImportSummary::default();
+        let mut registered_units = None;
+
+        for record in records {
+            // `MeterReading` is NOT NULL, which would surface as the same constraint error as a duplicate.
+            let record = record.and_then(|record| match record.reading.meter_reading {
+                Some(_) => Ok(record),
+                None => Err(anyhow!("Missing meter reading at timestamp {}.", record.timestamp)),
+            });
+
+            let record = match record {
+                Ok(record) => record,
+                Err(error) if error.is::<ImportAborted>() => return Err(error),
+                Err(error) => {
+                    summary.rejected += 1;
+                    if summary.rejected <= MAX_REPORTED_REJECTIONS {
+                        println!("Rejected: {error}");
+                    }
+                    continue;
+                }
+            };
+
+            // e.g. the power columns of a Tasmota log may only be filled in from some row on.
+            if registered_units.as_ref() != Some(&record.reading.units()) {
+                database.update_meter_units(meter, &record.reading)?;
+                registered_units = Some(record.reading.units());
+            }
+
+            if database.insert_reading_at(meter, record.timestamp, &record.reading)? {
+                summary.inserted += 1;
+            } else {
+                summary.skipped += 1;
+            }
+        }
+
+        if summary.rejected > MAX_REPORTED_REJECTIONS {
+            println!("Rejected {} more rows.", summary.rejected - MAX_REPORTED_REJECTIONS);
+        }
+
+        Ok(summary)
+    })
+}
+
+/// Reads a delimited file, detecting whether `;`, tab or `,` is used as delimiter from its first line.
+///
+/// Yields each record together with its line number.
+fn csv_records(reader: impl Read + 'static) -> Result<impl Iterator<Item = Result<(u64, StringRecord), Error>>, Error> {
+    let mut reader = BufReader::new(reader);
+    let mut first_line = String::new();
+    reader.read_line(&mut first_line)?;
+
+    let delimiter = [b';', b'\t', b',']
+        .into_iter()
+        .max_by_key(|delimiter| first_line.bytes().filter(|byte| byte == delimiter).count())
+        .unwrap();
+
+    let records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(Cursor::new(first_line).chain(reader))
+        .into_records()
+        .map(|record| {
+            let record = record?;
+            let line = record.position().map(|position| position.line()).unwrap_or_default();
+
+            Ok((line, record))
+        });
+
+    Ok(records)
+}
+
+/// Parses a decimal number, accepting a decimal comma and thousands separators (`1.234,5`).
+fn parse_decimal(value: &str) -> Result<f64, Error> {
+    let normalized = match (value.rfind(','), value.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => value.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => value.replace(',', ""),
+        (Some(_), None) => value.replace(',', "."),
+        _ => value.to_string(),
+    };
+
+    normalized.parse().map_err(|_| anyhow!("Invalid number \"{value}\"."))
+}
+
+/// Parses a date time as written by spreadsheets and portals (local time), or anything accepted by `parse_timestamp`.
+fn parse_date_time(value: &str) -> Result<i64, Error> {
+    const FORMATS: &[&str] = &[
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+        "%d.%m.%Y %H:%M:%S",
+        "%d.%m.%Y %H:%M",
+        "%d/%m/%Y %H:%M:%S",
+        "%d/%m/%Y %H:%M",
+    ];
+
+    for format in FORMATS {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(value, format) {
+            return local_timestamp(date_time);
+        }
+    }
+
+    parse_timestamp(value)
+}
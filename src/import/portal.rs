@@ -0,0 +1,137 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Error};
+use csv::StringRecord;
+
+use crate::import::{csv_records, parse_date_time, parse_decimal, ImportAborted, ImportOptions, ImportRecord};
+use crate::meter_reading::MeterReading;
+use crate::unit::Unit;
+
+const INTERVAL_SECS: i64 = 15 * 60;
+
+/// Reads a utility portal export with one row per 15-minute interval: the start of the interval in the
+/// first column (or date and time in the first two columns) and the consumption in kWh in the last column.
+///
+/// As `Readings` stores the meter reading, the consumption is summed up starting at `initial_reading`.
+/// Each record is timestamped at the end of its interval. A leading header row is ignored.
+///
+/// A row without a time and a value is rejected. The consumption of a row with an unreadable time is carried
+/// forward to the next row, while an unreadable consumption aborts the import as every later meter reading would be off.
+pub fn records(reader: impl Read + 'static, options: &ImportOptions) -> Result<Box<dyn Iterator<Item = Result<ImportRecord, Error>>>, Error> {
+    let mut meter_reading = options.initial_reading;
+
+    let records = csv_records(reader)?
+        .filter(|record| !matches!(record, Ok((1, record)) if is_header(record)))
+        .map(move |record| {
+            let (line, record) = record?;
+            let Interval { start, consumption } = parse_record(&record).map_err(|error| anyhow!("Line {line}: {error}"))?;
+
+            let consumption = consumption.map_err(|error| ImportAborted(format!("Line {line}: {error}")))?;
+            meter_reading += consumption * 1000.0;
+
+            let start = start.map_err(|error| anyhow!("Line {line}: {error} Its consumption is carried forward."))?;
+
+            let reading = MeterReading {
+                meter_reading: Some(meter_reading),
+                meter_reading_unit: Some(Unit::WattHour),
+                ..Default::default()
+            };
+
+            Ok(ImportRecord { timestamp: start + INTERVAL_SECS, reading })
+        });
+
+    Ok(Box::new(records))
+}
+
+fn is_header(record: &StringRecord) -> bool {
+    !matches!(parse_record(record), Ok(Interval { start: Ok(_), consumption: Ok(_) }))
+}
+
+/// The start of an interval and its consumption in kWh, which are parsed independently.
+struct Interval {
+    start: Result<i64, Error>,
+    consumption: Result<f64, Error>,
+}
+
+fn parse_record(record: &StringRecord) -> Result<Interval, Error> {
+    let fields = record.iter().filter(|field| !field.is_empty()).collect::<Vec<_>>();
+    if fields.len() < 2 {
+        bail!("Expected a time and a value.");
+    }
+
+    Ok(Interval {
+        start: parse_start(&fields),
+        consumption: parse_decimal(fields[fields.len() - 1]),
+    })
+}
+
+fn parse_start(fields: &[&str]) -> Result<i64, Error> {
+    // an ISO date alone parses as midnight, so a separate time column has to be tried first.
+    if fields.len() >= 3 {
+        if let Ok(start) = parse_date_time(&format!("{} {}", fields[0], fields[1])) {
+            return Ok(start);
+        }
+    }
+
+    parse_date_time(fields[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::use_test_time_zone;
+
+    fn read(csv: &'static str) -> Vec<Result<ImportRecord, Error>> {
+        use_test_time_zone();
+        records(csv.as_bytes(), &ImportOptions { initial_reading: 1000.0 }).unwrap().collect()
+    }
+
+    fn timestamps(records: &[Result<ImportRecord, Error>]) -> Vec<i64> {
+        records.iter().map(|record| record.as_ref().unwrap().timestamp).collect()
+    }
+
+    #[test]
+    fn sums_up_the_consumption_from_separate_date_and_time_columns() {
+        let records = read("Datum;Uhrzeit;Verbrauch (kWh)\n01.03.2024;00:00;0,25\n01.03.2024;00:15;0,5\n");
+
+        let start = parse_date_time("2024-03-01 00:00").unwrap();
+        assert_eq!(timestamps(&records), [start + INTERVAL_SECS, start + 2 * INTERVAL_SECS]);
+        assert_eq!(records[0].as_ref().unwrap().reading.meter_reading, Some(1250.0));
+        assert_eq!(records[1].as_ref().unwrap().reading.meter_reading, Some(1750.0));
+    }
+
+    #[test]
+    fn prefers_the_time_column_over_an_iso_date() {
+        // "2024-03-01" alone would be taken as midnight.
+        let records = read("2024-03-01,12:00,0.1\n2024-03-01,12:15,0.1\n");
+
+        let start = parse_date_time("2024-03-01 12:00").unwrap();
+        assert_eq!(timestamps(&records), [start + INTERVAL_SECS, start + 2 * INTERVAL_SECS]);
+    }
+
+    #[test]
+    fn reads_a_single_date_time_column() {
+        let records = read("Start\tkWh\n2024-03-01 12:00\t0.1\n");
+
+        assert_eq!(timestamps(&records), [parse_date_time("2024-03-01 12:15").unwrap()]);
+    }
+
+    #[test]
+    fn rejects_short_rows_and_carries_the_consumption_of_unreadable_times_forward() {
+        let records = read("2024-03-01 12:00;0.1\n2024-03-01 12:15\nnoon;0.2\n2024-03-01 12:45;0.3\n");
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[1].as_ref().err().unwrap().to_string(), "Line 2: Expected a time and a value.");
+        assert!(!records[1].as_ref().err().unwrap().is::<ImportAborted>());
+        assert!(!records[2].as_ref().err().unwrap().is::<ImportAborted>());
+        assert_eq!(records[3].as_ref().unwrap().reading.meter_reading, Some(1600.0));
+    }
+
+    #[test]
+    fn aborts_on_an_unreadable_consumption() {
+        let records = read("2024-03-01 12:00;0.1\n2024-03-01 12:15;n/a\n");
+
+        assert!(records[0].is_ok());
+        assert!(records[1].as_ref().err().unwrap().is::<ImportAborted>());
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Error};
+use clap_derive::ValueEnum;
+use serde_json::Value as JsonValue;
+
+use crate::import::{csv_records, parse_date_time, parse_decimal};
+use crate::prices::SpotPrice;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PriceFormat {
+    /// Rows of `<start>;<price>` or `<start>;<end>;<price>`, an optional header row is skipped
+    Csv,
+    /// The aWATTar API response, or an array of `{"start": .., "end": .., "price": ..}` objects
+    Json,
+}
+
+/// The amount of energy the prices of a file refer to.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PriceUnit {
+    Kwh,
+    Mwh,
+}
+
+impl PriceUnit {
+    fn per_kwh(&self, price: f64) -> f64 {
+        match self {
+            PriceUnit::Kwh => price,
+            PriceUnit::Mwh => price / 1000.0,
+        }
+    }
+}
+
+/// Reads the prices of a file, converted to a price per kWh.
+///
+/// Prices without an end are valid for `duration` seconds.
+pub fn read_prices(format: PriceFormat, reader: impl Read + 'static, unit: PriceUnit, duration: i64) -> Result<Vec<SpotPrice>, Error> {
+    let mut prices = match format {
+        PriceFormat::Csv => read_csv(reader, unit, duration)?,
+        PriceFormat::Json => read_json(reader, unit, duration)?,
+    };
+
+    if let Some(price) = prices.iter().find(|price| price.duration <= 0) {
+        bail!("The price starting at {} ends before it starts.", price.timestamp);
+    }
+
+    prices.sort_by_key(|price| price.timestamp);
+    if let Some(pair) = prices.windows(2).find(|pair| pair[0].timestamp + pair[0].duration > pair[1].timestamp) {
+        bail!("The prices starting at {} and {} overlap.", pair[0].timestamp, pair[1].timestamp);
+    }
+
+    Ok(prices)
+}
+
+fn read_csv(reader: impl Read + 'static, unit: PriceUnit, duration: i64) -> Result<Vec<SpotPrice>, Error> {
+    let mut prices = Vec::new();
+
+    for record in csv_records(reader)? {
+        let (line, record) = record?;
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+
+        let price = match record.len() {
+            2 => parse_date_time(&record[0]).and_then(|start| {
+                Ok(SpotPrice { timestamp: start, duration, price: unit.per_kwh(parse_decimal(&record[1])?) })
+            }),
+            3 => parse_date_time(&record[0]).and_then(|start| {
+                Ok(SpotPrice { timestamp: start, duration: parse_date_time(&record[1])? - start, price: unit.per_kwh(parse_decimal(&record[2])?) })
+            }),
+            columns => Err(anyhow!("Expected 2 or 3 columns, found {columns}.")),
+        };
+
+        match price {
+            Ok(price) => prices.push(price),
+            // the first row may be a header.
+            Err(_) if line == 1 => {}
+            Err(error) => bail!("Line {line}: {error}"),
+        }
+    }
+
+    Ok(prices)
+}
+
+fn read_json(reader: impl Read, unit: PriceUnit, duration: i64) -> Result<Vec<SpotPrice>, Error> {
+    let json: JsonValue = serde_json::from_reader(reader)?;
+
+    // aWATTar: {"unit": "Eur/MWh", "data": [{"start_timestamp": <ms>, "end_timestamp": <ms>, "marketprice": 85.2, "unit": "Eur/MWh"}]}
+    if let Some(data) = json.get("data").and_then(JsonValue::as_array) {
+        return data.iter()
+            .map(|entry| {
+                let millis = |key: &str| entry.get(key).and_then(JsonValue::as_i64).ok_or_else(|| anyhow!("Missing \"{key}\"."));
+                let start = millis("start_timestamp")? / 1000;
+                let end = millis("end_timestamp")? / 1000;
+                let price = entry.get("marketprice").and_then(JsonValue::as_f64).ok_or_else(|| anyhow!("Missing \"marketprice\"."))?;
+
+                let unit = match entry.get("unit").and_then(JsonValue::as_str) {
+                    Some(entry_unit) if entry_unit.to_lowercase().ends_with("/mwh") => PriceUnit::Mwh,
+                    Some(entry_unit) if entry_unit.to_lowercase().ends_with("/kwh") => PriceUnit::Kwh,
+                    _ => unit,
+                };
+
+                Ok(SpotPrice { timestamp: start, duration: end - start, price: unit.per_kwh(price) })
+            })
+            .collect();
+    }
+
+    let Some(entries) = json.as_array() else {
+        bail!("Expected an array of prices or an aWATTar response.");
+    };
+
+    entries.iter()
+        .map(|entry| {
+            let time = |key: &str| match entry.get(key) {
+                Some(JsonValue::Number(number)) => number.as_i64().ok_or_else(|| anyhow!("Invalid \"{key}\".")).map(Some),
+                Some(JsonValue::String(value)) => parse_date_time(value).map(Some),
+                None | Some(JsonValue::Null) => Ok(None),
+                Some(_) => Err(anyhow!("Invalid \"{key}\".")),
+            };
+
+            let start = time("start")?.ok_or_else(|| anyhow!("Missing \"start\"."))?;
+            let end = time("end")?.unwrap_or(start + duration);
+            let price = entry.get("price").and_then(JsonValue::as_f64).ok_or_else(|| anyhow!("Missing \"price\"."))?;
+
+            Ok(SpotPrice { timestamp: start, duration: end - start, price: unit.per_kwh(price) })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::use_test_time_zone;
+
+    fn read(format: PriceFormat, contents: &'static str, unit: PriceUnit) -> Result<Vec<SpotPrice>, Error> {
+        use_test_time_zone();
+        read_prices(format, contents.as_bytes(), unit, 3600)
+    }
+
+    fn summary(prices: &[SpotPrice]) -> Vec<(i64, i64, f64)> {
+        prices.iter().map(|price| (price.timestamp, price.duration, price.price)).collect()
+    }
+
+    #[test]
+    fn reads_csv_with_start_and_optional_end() {
+        let prices = read(PriceFormat::Csv, "Start;Price (EUR/MWh)\n2024-03-01 01:00;85,5\n2024-03-01 00:00;-10\n", PriceUnit::Mwh).unwrap();
+
+        let midnight = parse_date_time("2024-03-01 00:00").unwrap();
+        assert_eq!(summary(&prices), [(midnight, 3600, -0.01), (midnight + 3600, 3600, 0.0855)]);
+
+        let prices = read(PriceFormat::Csv, "2024-03-01 00:00,2024-03-01 00:15,0.25\n", PriceUnit::Kwh).unwrap();
+        assert_eq!(summary(&prices), [(midnight, 900, 0.25)]);
+    }
+
+    #[test]
+    fn rejects_invalid_csv_rows_after_the_header() {
+        let error = read(PriceFormat::Csv, "Start;Price\n2024-03-01 00:00;0.1\n2024-03-01 01:00\n", PriceUnit::Kwh).err().unwrap();
+
+        assert_eq!(error.to_string(), "Line 3: Expected 2 or 3 columns, found 1.");
+    }
+
+    #[test]
+    fn rejects_overlapping_and_reversed_prices() {
+        let error = read(PriceFormat::Csv, "2024-03-01 00:00;2024-03-01 02:00;0.1\n2024-03-01 01:00;0.2\n", PriceUnit::Kwh).err().unwrap();
+        assert!(error.to_string().contains("overlap"), "{error}");
+
+        let error = read(PriceFormat::Csv, "2024-03-01 01:00;2024-03-01 00:00;0.1\n", PriceUnit::Kwh).err().unwrap();
+        assert!(error.to_string().contains("ends before it starts"), "{error}");
+    }
+
+    #[test]
+    fn reads_the_awattar_response_in_its_own_unit() {
+        let prices = read(
+            PriceFormat::Json,
+            r#"{"object": "list", "data": [{"start_timestamp": 1709251200000, "end_timestamp": 1709254800000, "marketprice": 85.2, "unit": "Eur/MWh"}]}"#,
+            PriceUnit::Kwh,
+        ).unwrap();
+
+        assert_eq!(summary(&prices), [(1_709_251_200, 3600, 0.0852)]);
+    }
+
+    #[test]
+    fn reads_a_json_array_of_prices() {
+        let prices = read(
+            PriceFormat::Json,
+            r#"[{"start": 1709251200, "end": 1709252100, "price": 0.2}, {"start": "2024-03-01T00:15:00Z", "price": 0.3}]"#,
+            PriceUnit::Kwh,
+        ).unwrap();
+
+        assert_eq!(summary(&prices), [(1_709_251_200, 900, 0.2), (1_709_252_100, 3600, 0.3)]);
+
+        assert!(read(PriceFormat::Json, r#"{"prices": []}"#, PriceUnit::Kwh).is_err());
+        assert!(read(PriceFormat::Json, r#"[{"start": true, "price": 0.3}]"#, PriceUnit::Kwh).is_err());
+    }
+}
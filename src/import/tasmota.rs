@@ -0,0 +1,146 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Error};
+use csv::StringRecord;
+
+use crate::import::{csv_records, parse_date_time, parse_decimal, ImportRecord};
+use crate::meter_reading::MeterReading;
+use crate::unit::Unit;
+
+// accepted header names (compared case-insensitively) for each column of `Readings`.
+const TIME_COLUMNS: &[&str] = &["time", "timestamp", "date", "datetime"];
+const TOTAL_COLUMNS: &[&str] = &["total_in", "total", "energy_in", "energy_total", "e_in"];
+const LINE_ONE_COLUMNS: &[&str] = &["power_l1", "power_p1", "l1", "p1"];
+const LINE_TWO_COLUMNS: &[&str] = &["power_l2", "power_p2", "l2", "p2"];
+const LINE_THREE_COLUMNS: &[&str] = &["power_l3", "power_p3", "l3", "p3"];
+
+/// Positions of the recognized columns within a row.
+struct Columns {
+    time: usize,
+    total: usize,
+    line_one: Option<usize>,
+    line_two: Option<usize>,
+    line_three: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &StringRecord) -> Result<Self, Error> {
+        let find = |names: &[&str]| header.iter().position(|field| names.contains(&field.to_lowercase().as_str()));
+
+        Ok(Columns {
+            time: find(TIME_COLUMNS).ok_or_else(|| anyhow!("No time column found in header."))?,
+            total: find(TOTAL_COLUMNS).ok_or_else(|| anyhow!("No energy total column found in header."))?,
+            line_one: find(LINE_ONE_COLUMNS),
+            line_two: find(LINE_TWO_COLUMNS),
+            line_three: find(LINE_THREE_COLUMNS),
+        })
+    }
+}
+
+/// Reads a CSV log written by a Tasmota SML script, with the energy total in kWh and the power per phase in W.
+///
+/// The columns are recognized by the header row, see the `*_COLUMNS` constants.
+pub fn records(reader: impl Read + 'static) -> Result<Box<dyn Iterator<Item = Result<ImportRecord, Error>>>, Error> {
+    let mut records = csv_records(reader)?;
+
+    let Some(header) = records.next() else {
+        return Ok(Box::new(std::iter::empty()));
+    };
+    let columns = Columns::from_header(&header?.1)?;
+
+    let records = records.map(move |record| {
+        let (line, record) = record?;
+        parse_record(&columns, &record).map_err(|error| anyhow!("Line {line}: {error}"))
+    });
+
+    Ok(Box::new(records))
+}
+
+fn parse_record(columns: &Columns, record: &StringRecord) -> Result<ImportRecord, Error> {
+    let field = |index: usize| record.get(index).filter(|field| !field.is_empty());
+    let power = |index: Option<usize>| -> Result<Option<i32>, Error> {
+        match index.and_then(field) {
+            Some(value) => Ok(Some(parse_decimal(value)?.round() as i32)),
+            None => Ok(None),
+        }
+    };
+
+    let Some(time) = field(columns.time) else {
+        bail!("Missing time.");
+    };
+    let Some(total) = field(columns.total) else {
+        bail!("Missing energy total.");
+    };
+
+    let line_one = power(columns.line_one)?;
+    let line_two = power(columns.line_two)?;
+    let line_three = power(columns.line_three)?;
+
+    let reading = MeterReading {
+        meter_reading: Some(parse_decimal(total)? * 1000.0),
+        meter_reading_unit: Some(Unit::WattHour),
+        line_one,
+        line_one_unit: line_one.map(|_| Unit::Watt),
+        line_two,
+        line_two_unit: line_two.map(|_| Unit::Watt),
+        line_three,
+        line_three_unit: line_three.map(|_| Unit::Watt),
+        ..Default::default()
+    };
+
+    Ok(ImportRecord { timestamp: parse_date_time(time)?, reading })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::use_test_time_zone;
+
+    fn read(csv: &'static str) -> Vec<Result<ImportRecord, Error>> {
+        use_test_time_zone();
+        records(csv.as_bytes()).unwrap().collect()
+    }
+
+    #[test]
+    fn maps_the_columns_by_their_header() {
+        let records = read("Time,Power_L1,Total_in,Power_L3\n2024-03-01 12:00:00,230.4,1234.5,\n");
+
+        assert_eq!(records.len(), 1);
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(record.timestamp, parse_date_time("2024-03-01 12:00:00").unwrap());
+        assert_eq!(record.reading.meter_reading, Some(1_234_500.0));
+        assert_eq!(record.reading.line_one, Some(230));
+        assert_eq!(record.reading.line_one_unit, Some(Unit::Watt));
+        assert_eq!(record.reading.line_two, None);
+        // an empty field is a missing value.
+        assert_eq!(record.reading.line_three, None);
+        assert_eq!(record.reading.line_three_unit, None);
+    }
+
+    #[test]
+    fn reads_semicolons_and_decimal_commas() {
+        let records = read("date;e_in;l1;l2;l3\n01.03.2024 12:00;1.234,5;-100;0,4;12\n");
+
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(record.timestamp, parse_date_time("2024-03-01 12:00").unwrap());
+        assert_eq!(record.reading.meter_reading, Some(1_234_500.0));
+        assert_eq!(record.reading.line_one, Some(-100));
+        assert_eq!(record.reading.line_two, Some(0));
+        assert_eq!(record.reading.line_three, Some(12));
+    }
+
+    #[test]
+    fn rejects_rows_without_a_total() {
+        let records = read("Time,Total_in\n2024-03-01 12:00,\n2024-03-01 12:01,1234.5\n");
+
+        assert_eq!(records[0].as_ref().err().unwrap().to_string(), "Line 2: Missing energy total.");
+        assert!(records[1].is_ok());
+    }
+
+    #[test]
+    fn fails_without_a_total_column() {
+        let error = records("Time,Power_L1\n2024-03-01 12:00,100\n".as_bytes()).err().unwrap();
+
+        assert_eq!(error.to_string(), "No energy total column found in header.");
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::import::{csv_records, parse_decimal, ImportRecord};
+use crate::meter_reading::MeterReading;
+use crate::unit::Unit;
+
+/// Timestamps above this value are taken as milliseconds, which is what vzlogger and volkszaehler use.
+const MILLISECONDS_THRESHOLD: i64 = 100_000_000_000;
+
+/// Reads `<timestamp>;<meter reading in Wh>` rows as exported from a vzlogger / volkszaehler energy channel.
+///
+/// A leading header row and `#` comments are ignored.
+pub fn records(reader: impl Read + 'static) -> Result<Box<dyn Iterator<Item = Result<ImportRecord, Error>>>, Error> {
+    let records = csv_records(reader)?
+        .filter(|record| !matches!(record, Ok((line, record)) if is_ignored(*line, record)))
+        .map(|record| {
+            let (line, record) = record?;
+            parse_record(&record).map_err(|error| anyhow!("Line {line}: {error}"))
+        });
+
+    Ok(Box::new(records))
+}
+
+fn is_ignored(line: u64, record: &csv::StringRecord) -> bool {
+    let first = record.get(0).unwrap_or_default();
+
+    first.is_empty() || first.starts_with('#') || (line == 1 && first.parse::<f64>().is_err())
+}
+
+fn parse_record(record: &csv::StringRecord) -> Result<ImportRecord, Error> {
+    if record.len() < 2 {
+        bail!("Expected a timestamp and a value.");
+    }
+
+    let timestamp = record[0].parse::<f64>().map_err(|_| anyhow!("Invalid timestamp \"{}\".", &record[0]))? as i64;
+    let timestamp = if timestamp > MILLISECONDS_THRESHOLD { timestamp / 1000 } else { timestamp };
+
+    let reading = MeterReading {
+        meter_reading: Some(parse_decimal(&record[1])?),
+        meter_reading_unit: Some(Unit::WattHour),
+        ..Default::default()
+    };
+
+    Ok(ImportRecord { timestamp, reading })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(csv: &'static str) -> Vec<Result<ImportRecord, Error>> {
+        records(csv.as_bytes()).unwrap().collect()
+    }
+
+    #[test]
+    fn skips_the_header_and_comments_and_converts_milliseconds() {
+        let records = read("timestamp;value\n# channel 1\n1709251200000;1234,5\n1709251260000;1235\n");
+
+        assert_eq!(records.len(), 2);
+        let first = records[0].as_ref().unwrap();
+        assert_eq!(first.timestamp, 1_709_251_200);
+        assert_eq!(first.reading.meter_reading, Some(1234.5));
+        assert_eq!(records[1].as_ref().unwrap().timestamp, 1_709_251_260);
+    }
+
+    #[test]
+    fn detects_the_comma_delimiter_and_keeps_seconds() {
+        let records = read("1709251200,1234.5\n1709251260,1235.25\n");
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_ref().unwrap().timestamp, 1_709_251_200);
+        assert_eq!(records[1].as_ref().unwrap().reading.meter_reading, Some(1235.25));
+    }
+
+    #[test]
+    fn rejects_rows_naming_their_line() {
+        let records = read("1709251200;1234\nyesterday;1235\n1709251320\n1709251380;1236\n");
+
+        assert_eq!(records.len(), 4);
+        assert!(records[0].is_ok());
+        assert_eq!(records[1].as_ref().err().unwrap().to_string(), "Line 2: Invalid timestamp \"yesterday\".");
+        assert_eq!(records[2].as_ref().err().unwrap().to_string(), "Line 3: Expected a timestamp and a value.");
+        assert!(records[3].is_ok());
+    }
+}
@@ -0,0 +1,421 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Error};
+use serde::Deserialize;
+use ureq::Agent;
+
+use crate::storage::{encode_line, StoredReading};
+
+/// Attempts to write a batch before it goes to the spool.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Lines of the spool written per request once the server is reachable again.
+const SPOOL_CHUNK_LINES: usize = 5000;
+
+/// The `[influx]` section of the config. The exporter is enabled by setting `url`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxConfig {
+    /// Base URL of the server, e.g. `http://localhost:8086`.
+    pub url: Option<String>,
+    /// 1 for `/write` with `database`, `username` and `password`,
+    /// 2 for `/api/v2/write` with `org`, `bucket` and `token`.
+    pub version: u8,
+    pub database: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub token: Option<String>,
+    /// Readings written per request.
+    pub batch_size: usize,
+    /// Maximum number of seconds a reading waits for its batch to fill up.
+    pub flush_interval: u64,
+    /// File the lines are kept in while the server is unreachable.
+    /// Defaults to `influx-spool.lp` in the local data directory.
+    pub spool: Option<PathBuf>,
+    /// Size the spool may grow to, further batches are dropped.
+    pub spool_max_bytes: u64,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        InfluxConfig {
+            url: None,
+            version: 2,
+            database: None,
+            username: None,
+            password: None,
+            org: None,
+            bucket: None,
+            token: None,
+            batch_size: 60,
+            flush_interval: 10,
+            spool: None,
+            spool_max_bytes: 100_000_000,
+        }
+    }
+}
+
+impl InfluxConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.url.is_none() {
+            return Ok(());
+        }
+
+        match self.version {
+            1 if self.database.is_none() => bail!("InfluxDB 1 needs a `database`."),
+            2 if self.org.is_none() || self.bucket.is_none() => bail!("InfluxDB 2 needs an `org` and a `bucket`."),
+            1 | 2 => {}
+            version => bail!("Unknown InfluxDB version {version}, expected 1 or 2."),
+        }
+
+        if self.batch_size == 0 {
+            bail!("The InfluxDB batch size has to be at least 1.");
+        }
+
+        Ok(())
+    }
+}
+
+/// Why a write failed.
+enum WriteError {
+    /// The server rejected the lines, sending them again won't help.
+    Rejected(Error),
+    /// The server couldn't be reached or had a problem of its own.
+    Unavailable(Error),
+}
+
+/// Writes the readings to InfluxDB in batches.
+///
+/// Batches which can't be written after a few attempts are appended to the spool file,
+/// which is written before anything else once the server is reachable again.
+pub struct InfluxExporter {
+    config: InfluxConfig,
+    url: String,
+    spool: PathBuf,
+    /// How far the spool has been written. Only kept in memory, as writing
+    /// a line again after a restart just overwrites the point with the same values.
+    spool_offset: u64,
+    agent: Agent,
+}
+
+impl InfluxExporter {
+    /// Returns `None` if no server is configured.
+    pub fn new(config: &InfluxConfig) -> Result<Option<Self>, Error> {
+        let Some(url) = &config.url else {
+            return Ok(None);
+        };
+
+        let url = url.trim_end_matches('/');
+        let url = match config.version {
+            1 => format!("{url}/write"),
+            _ => format!("{url}/api/v2/write"),
+        };
+
+        let spool = match &config.spool {
+            Some(spool) => spool.clone(),
+            None => {
+                let mut path = dirs::data_local_dir().ok_or_else(|| anyhow!("Could not find the local data directory."))?;
+                path.push("rusty-power-meter");
+                fs::create_dir_all(&path)?;
+                path.push("influx-spool.lp");
+                path
+            }
+        };
+
+        let agent = Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .build()
+            .into();
+
+        Ok(Some(InfluxExporter {
+            config: config.clone(),
+            url,
+            spool,
+            spool_offset: 0,
+            agent,
+        }))
+    }
+
+    /// Exports readings until the sender is dropped, writing what's left of the last batch.
+    pub fn enter(mut self, readings: Receiver<StoredReading>) {
+        let flush_interval = Duration::from_secs(self.config.flush_interval);
+        let mut batch = Vec::new();
+        let mut deadline = None;
+
+        loop {
+            let timeout = deadline.map_or(flush_interval, |deadline: Instant| deadline.saturating_duration_since(Instant::now()));
+
+            match readings.recv_timeout(timeout) {
+                Ok(StoredReading { timestamp, meter, reading }) => {
+                    batch.extend(encode_line(&meter, timestamp, &reading));
+                    deadline.get_or_insert_with(|| Instant::now() + flush_interval);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush(std::mem::take(&mut batch));
+                    break;
+                }
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) || batch.len() >= self.config.batch_size {
+                self.flush(std::mem::take(&mut batch));
+                deadline = None;
+            }
+        }
+    }
+
+    fn flush(&mut self, batch: Vec<String>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        // the spool goes first, so the lines arrive roughly in order.
+        if let Err(error) = self.write_spool() {
+            println!("Warning: InfluxDB is unavailable, spooling {} readings: {error}", batch.len());
+            self.spool(&batch);
+            return;
+        }
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.write(&batch.concat()) {
+                Ok(()) => return,
+                Err(WriteError::Rejected(error)) => {
+                    println!("Warning: InfluxDB rejected {} readings: {error}", batch.len());
+                    return;
+                }
+                Err(WriteError::Unavailable(error)) if attempt == MAX_ATTEMPTS => {
+                    println!("Warning: InfluxDB is unavailable, spooling {} readings: {error}", batch.len());
+                    self.spool(&batch);
+                }
+                Err(WriteError::Unavailable(_)) => thread::sleep(Duration::from_secs(1 << attempt)),
+            }
+        }
+    }
+
+    /// Writes the spooled lines in chunks, stopping at the first chunk which couldn't be written.
+    ///
+    /// Only one chunk is read at a time, so a large spool neither has to fit into memory
+    /// nor is read as a whole while the server is still unavailable.
+    fn write_spool(&mut self) -> Result<(), Error> {
+        let mut reader = match File::open(&self.spool) {
+            Ok(file) => BufReader::new(file),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        reader.seek(SeekFrom::Start(self.spool_offset))?;
+
+        let mut written = 0;
+        loop {
+            let mut chunk = String::new();
+            let mut lines = 0;
+            while lines < SPOOL_CHUNK_LINES && reader.read_line(&mut chunk)? > 0 {
+                lines += 1;
+            }
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            // a line cut off by a crash is completed, so it doesn't run into the next one.
+            if !chunk.ends_with('\n') {
+                chunk.push('\n');
+            }
+
+            match self.write(&chunk) {
+                Ok(()) => written += lines,
+                Err(WriteError::Rejected(error)) => {
+                    println!("Warning: InfluxDB rejected {lines} spooled readings: {error}");
+                }
+                Err(WriteError::Unavailable(error)) => return Err(error),
+            }
+
+            self.spool_offset = reader.stream_position()?;
+        }
+
+        if written > 0 {
+            println!("Wrote {written} spooled readings to InfluxDB.");
+        }
+
+        fs::remove_file(&self.spool)?;
+        self.spool_offset = 0;
+        Ok(())
+    }
+
+    fn spool(&self, batch: &[String]) {
+        let lines = batch.concat();
+
+        let size = match fs::metadata(&self.spool) {
+            Ok(metadata) => metadata.len(),
+            Err(error) if error.kind() == ErrorKind::NotFound => 0,
+            Err(error) => {
+                println!("Warning: Could not spool to {}, {} readings are lost: {error}", self.spool.display(), batch.len());
+                return;
+            }
+        };
+
+        if size + lines.len() as u64 > self.config.spool_max_bytes {
+            println!("Warning: The spool {} is full, {} readings are lost.", self.spool.display(), batch.len());
+            return;
+        }
+
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.spool)
+            .and_then(|mut file| file.write_all(lines.as_bytes()));
+
+        if let Err(error) = result {
+            println!("Warning: Could not spool to {}, {} readings are lost: {error}", self.spool.display(), batch.len());
+        }
+    }
+
+    fn write(&self, body: &str) -> Result<(), WriteError> {
+        let mut request = self.agent.post(&self.url).query("precision", "s");
+
+        request = match self.config.version {
+            1 => {
+                request = request.query("db", self.config.database.as_deref().unwrap_or_default());
+                match (&self.config.username, &self.config.password) {
+                    (Some(username), password) => request.query("u", username).query("p", password.as_deref().unwrap_or_default()),
+                    _ => request,
+                }
+            }
+            _ => {
+                request = request
+                    .query("org", self.config.org.as_deref().unwrap_or_default())
+                    .query("bucket", self.config.bucket.as_deref().unwrap_or_default());
+                match &self.config.token {
+                    Some(token) => request.header("Authorization", format!("Token {token}")),
+                    None => request,
+                }
+            }
+        };
+
+        match request.content_type("text/plain; charset=utf-8").send(body) {
+            Ok(_) => Ok(()),
+            // too many requests is worth another try, other client errors aren't.
+            Err(ureq::Error::StatusCode(status)) if (400..500).contains(&status) && status != 429 => {
+                Err(WriteError::Rejected(anyhow!("HTTP status {status}")))
+            }
+            Err(error) => Err(WriteError::Unavailable(error.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::meter_reading::MeterReading;
+    use crate::test_server;
+    use crate::unit::Unit;
+
+    use super::*;
+
+    fn exporter(url: &str, version: u8, name: &str) -> InfluxExporter {
+        let config = InfluxConfig {
+            url: Some(format!("{url}/")),
+            version,
+            database: Some("power".to_string()),
+            username: Some("meter".to_string()),
+            password: Some("secret".to_string()),
+            org: Some("home".to_string()),
+            bucket: Some("power".to_string()),
+            token: Some("secret".to_string()),
+            batch_size: 2,
+            spool: Some(std::env::temp_dir().join(format!("influx-spool-{}-{name}.lp", std::process::id()))),
+            ..InfluxConfig::default()
+        };
+
+        InfluxExporter::new(&config).unwrap().unwrap()
+    }
+
+    fn reading(timestamp: i64, meter_reading: f64, line_one: i32) -> StoredReading {
+        StoredReading {
+            timestamp,
+            meter: "default".to_string(),
+            reading: MeterReading {
+                meter_reading: Some(meter_reading),
+                meter_reading_unit: Some(Unit::WattHour),
+                line_one: Some(line_one),
+                line_one_unit: Some(Unit::Watt),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn writes_batches_in_line_protocol() {
+        let (url, requests) = test_server::serve(204);
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send(reading(1711000000, 1234.5, 230)).unwrap();
+        sender.send(reading(1711000001, 1234.75, -15)).unwrap();
+        drop(sender);
+        exporter(&url, 2, "v2").enter(receiver);
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.request_line, "POST /api/v2/write?precision=s&org=home&bucket=power HTTP/1.1");
+        assert_eq!(request.header("authorization"), Some("Token secret"));
+        assert_eq!(request.header("content-type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(
+            request.body,
+            "readings,meter=default,meter_reading_unit=Wh,line_one_unit=W meter_reading=1234.5,line_one=230i 1711000000\n\
+             readings,meter=default,meter_reading_unit=Wh,line_one_unit=W meter_reading=1234.75,line_one=-15i 1711000001\n"
+        );
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn authenticates_with_query_parameters_for_version_one() {
+        let (url, requests) = test_server::serve(204);
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send(reading(1711000000, 1234.5, 230)).unwrap();
+        drop(sender);
+        exporter(&url, 1, "v1").enter(receiver);
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.request_line, "POST /write?precision=s&db=power&u=meter&p=secret HTTP/1.1");
+        assert_eq!(request.header("authorization"), None);
+    }
+
+    #[test]
+    fn writes_the_spool_in_chunks_before_the_batch() {
+        let (url, requests) = test_server::serve(204);
+        let mut exporter = exporter(&url, 2, "spool");
+
+        let spooled = (0..SPOOL_CHUNK_LINES + 1)
+            .map(|index| reading(1710000000 + index as i64, 1000.0, 100))
+            .map(|reading| encode_line(&reading.meter, reading.timestamp, &reading.reading).unwrap())
+            .collect::<Vec<_>>();
+        exporter.spool(&spooled);
+
+        let batch = encode_line("default", 1711000000, &reading(1711000000, 1234.5, 230).reading).unwrap();
+        exporter.flush(vec![batch.clone()]);
+
+        let bodies = requests.iter().take(3).map(|request| request.body).collect::<Vec<_>>();
+        assert_eq!(bodies, [spooled[..SPOOL_CHUNK_LINES].concat(), spooled[SPOOL_CHUNK_LINES].clone(), batch]);
+        assert!(!exporter.spool.exists());
+    }
+    #[test]
+    fn stops_spooling_at_the_size_limit() {
+        let mut exporter = exporter("http://127.0.0.1:1", 2, "limit");
+        let line = encode_line("default", 1711000000, &reading(1711000000, 1234.5, 230).reading).unwrap();
+        exporter.config.spool_max_bytes = 2 * line.len() as u64;
+
+        for _ in 0..3 {
+            exporter.spool(std::slice::from_ref(&line));
+        }
+
+        assert_eq!(fs::read_to_string(&exporter.spool).unwrap(), line.repeat(2));
+        fs::remove_file(&exporter.spool).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::watch;
+
+use crate::storage::StoredReading;
+
+/// How long a request for a newer reading waits before giving up.
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A reading together with when it was received and its sequence number, which counts the readings since the start.
+pub struct Latest {
+    /// Tells the sequence numbers of different runs apart, as they start again at 1.
+    pub run: u64,
+    pub sequence: u64,
+    pub received: Instant,
+    pub reading: StoredReading,
+}
+
+impl Latest {
+    /// The entity tag of the reading, unique across restarts.
+    pub fn etag(&self) -> String {
+        format!("\"{:x}-{}\"", self.run, self.sequence)
+    }
+}
+
+/// Keeps the latest reading for any number of readers, reading it doesn't take it out.
+pub struct LatestReading {
+    run: u64,
+    sender: watch::Sender<Option<Arc<Latest>>>,
+}
+
+impl Default for LatestReading {
+    fn default() -> Self {
+        LatestReading {
+            run: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_nanos() as u64),
+            sender: watch::Sender::new(None),
+        }
+    }
+}
+
+impl LatestReading {
+    pub fn update(&self, reading: StoredReading) {
+        self.sender.send_modify(|latest| {
+            let sequence = latest.as_ref().map_or(1, |latest| latest.sequence + 1);
+            *latest = Some(Arc::new(Latest { run: self.run, sequence, received: Instant::now(), reading }));
+        });
+    }
+
+    pub fn get(&self) -> Option<Arc<Latest>> {
+        self.sender.borrow().clone()
+    }
+
+    /// Returns the latest reading right away unless it is the one with `sequence`,
+    /// otherwise waits for the next one and returns `None` if it doesn't arrive in time.
+    ///
+    /// A `sequence` above the latest one is from before a restart, so it is answered right away as well.
+    pub async fn newer_than(&self, sequence: u64) -> Option<Arc<Latest>> {
+        let mut receiver = self.sender.subscribe();
+        let newer = receiver.wait_for(|latest| latest.as_ref().is_some_and(|latest| latest.sequence != sequence));
+
+        // bound before returning, the borrow of the receiver has to end first.
+        let latest = match tokio::time::timeout(LONG_POLL_TIMEOUT, newer).await {
+            Ok(Ok(latest)) => latest.clone(),
+            _ => None,
+        };
+
+        latest
+    }
+}
+
+/// The readings of the last minutes at full resolution, whether or not the store has them yet.
+pub struct RecentReadings {
+    /// Seconds a reading is kept.
+    retention: i64,
+    readings: Mutex<VecDeque<StoredReading>>,
+}
+
+impl RecentReadings {
+    pub fn new(retention: i64) -> Self {
+        RecentReadings {
+            retention,
+            readings: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn retention(&self) -> i64 {
+        self.retention
+    }
+
+    /// Adds a reading, dropping those which are older than the retention compared to it.
+    pub fn push(&self, reading: StoredReading) {
+        let mut readings = self.readings.lock().unwrap();
+        let oldest = reading.timestamp - self.retention;
+
+        readings.push_back(reading);
+        while readings.front().is_some_and(|reading| reading.timestamp < oldest) {
+            readings.pop_front();
+        }
+    }
+
+    /// Returns the readings taken at or after `from`, oldest first.
+    pub fn since(&self, from: i64, meter: Option<&str>) -> Vec<StoredReading> {
+        self.readings.lock().unwrap().iter()
+            .filter(|reading| reading.timestamp >= from && meter.is_none_or(|meter| reading.meter == meter))
+            .cloned()
+            .collect()
+    }
+}
@@ -9,6 +9,25 @@ mod meter_reading;
 mod cli;
 mod database;
 mod core_loop;
+mod latest_reading;
 mod server;
+mod timestamp;
+mod export;
+mod import;
+mod storage;
+mod consumption;
+mod config;
+mod tariff;
+mod cost;
+mod prices;
+mod stats;
+mod base_load;
+mod demand;
+mod imbalance;
+mod alerts;
+mod mqtt;
+mod influx;
+#[cfg(test)]
+mod test_server;
 
 fn main() -> Result<(), Error> { RootCommand::parse().run() }
\ No newline at end of file
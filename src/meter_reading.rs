@@ -8,7 +8,7 @@ use sml_rs::parser::complete::{File, MessageBody};
 use crate::obis_code::ObisCode;
 use crate::unit::Unit;
 
-#[derive(Serialize)]
+#[derive(Clone, Default, Serialize)]
 pub struct MeterReading {
     pub meter_time: Option<u32>,
     
@@ -56,7 +56,7 @@ impl MeterReading {
         };
         
         for entry in &get_list_response.val_list {
-            let obis_code = ObisCode::try_from_octet_str(&entry.obj_name).map_err(|e| anyhow!("{e:?}"));
+            let obis_code = ObisCode::try_from_octet_str(entry.obj_name).map_err(|e| anyhow!("{e:?}"));
             let obis_code = match obis_code {
                 Ok(obis_code) => obis_code,
                 Err(e) => {
@@ -127,6 +127,26 @@ impl MeterReading {
         Ok(meter_values)
     }
     
+    /// The units of the meter reading and the three lines, in that order.
+    pub fn units(&self) -> [Option<Unit>; 4] {
+        [
+            self.meter_reading_unit.clone(),
+            self.line_one_unit.clone(),
+            self.line_two_unit.clone(),
+            self.line_three_unit.clone(),
+        ]
+    }
+
+    /// The power of all lines together, negative when feeding into the grid. `None` if no line was read.
+    pub fn total_power(&self) -> Option<i64> {
+        let lines = [self.line_one, self.line_two, self.line_three];
+        if lines.iter().all(Option::is_none) {
+            return None;
+        }
+
+        Some(lines.iter().flatten().map(|&power| power as i64).sum())
+    }
+
     pub fn display_compact(&self) -> String {
         format!("{}s, {} {}, {} {}, {} {}, {} {}", 
             map_unknown(&self.meter_time),
@@ -152,10 +172,10 @@ fn map_unknown(option: &Option<impl Display>) -> String {
 
 impl Display for MeterReading {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Meter Reading: {} {}\n", map_unknown(&self.meter_reading), map_unknown(&self.meter_reading_unit))?;
-        write!(f, "Meter Time: {}\n", map_unknown(&self.meter_time))?;
-        write!(f, "Line One: {} {}\n", map_unknown(&self.line_one), map_unknown(&self.line_one_unit))?;
-        write!(f, "Line Two: {} {}\n", map_unknown(&self.line_two), map_unknown(&self.line_two_unit))?;
-        write!(f, "Line Three: {} {}\n", map_unknown(&self.line_three), map_unknown(&self.line_three_unit))
+        writeln!(f, "Meter Reading: {} {}", map_unknown(&self.meter_reading), map_unknown(&self.meter_reading_unit))?;
+        writeln!(f, "Meter Time: {}", map_unknown(&self.meter_time))?;
+        writeln!(f, "Line One: {} {}", map_unknown(&self.line_one), map_unknown(&self.line_one_unit))?;
+        writeln!(f, "Line Two: {} {}", map_unknown(&self.line_two), map_unknown(&self.line_two_unit))?;
+        writeln!(f, "Line Three: {} {}", map_unknown(&self.line_three), map_unknown(&self.line_three_unit))
     }
 }
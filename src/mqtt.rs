@@ -0,0 +1,520 @@
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::meter_reading::MeterReading;
+use crate::storage::StoredReading;
+use crate::unit::Unit;
+
+/// How long to wait before connecting again after the connection to the broker failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes queued while the broker is unreachable, further ones are dropped.
+const QUEUE_CAPACITY: usize = 100;
+
+/// The `[mqtt]` section of the config. Publishing is enabled by setting `host`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic of each field, `{meter}` and `{field}` are replaced by the meter and field name.
+    pub topic: String,
+    /// Topic of the whole reading as JSON, `{meter}` is replaced by the meter name.
+    pub json_topic: Option<String>,
+    /// Topic of the `online`/`offline` status, which the broker sets to `offline` when the connection is lost.
+    pub status_topic: String,
+    /// Whether the broker keeps the last value of each topic for new subscribers.
+    pub retain: bool,
+    /// 0, 1 or 2.
+    pub qos: u8,
+    /// Minimum number of seconds between two published readings, 0 publishes every reading.
+    pub interval: i64,
+    /// Whether to announce the meter and its sensors to Home Assistant.
+    pub discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: None,
+            port: 1883,
+            client_id: "rusty-power-meter".to_string(),
+            username: None,
+            password: None,
+            topic: "rusty-power-meter/{meter}/{field}".to_string(),
+            json_topic: None,
+            status_topic: "rusty-power-meter/{meter}/status".to_string(),
+            retain: true,
+            qos: 0,
+            interval: 0,
+            discovery: false,
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+impl MqttConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.topic.contains("{field}") {
+            bail!("The MQTT topic has to contain `{{field}}`.");
+        }
+
+        if self.qos > 2 {
+            bail!("The MQTT QoS has to be 0, 1 or 2.");
+        }
+
+        if self.interval < 0 {
+            bail!("The MQTT interval can't be negative.");
+        }
+
+        Ok(())
+    }
+
+    fn qos(&self) -> QoS {
+        match self.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// Publishes the readings of a meter to an MQTT broker.
+pub struct MqttPublisher {
+    config: MqttConfig,
+    meter: String,
+    client: Client,
+}
+
+impl MqttPublisher {
+    /// Starts connecting to the broker in the background, returns `None` if no broker is configured.
+    pub fn connect(config: &MqttConfig, meter: &str) -> Option<Self> {
+        let host = config.host.as_ref()?;
+        let status_topic = config.status_topic.replace("{meter}", meter);
+
+        let mut options = MqttOptions::new(&config.client_id, host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(&status_topic, "offline", config.qos(), true));
+
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+
+        // iterating the connection drives it, including reconnecting after errors.
+        let status_client = client.clone();
+        let qos = config.qos();
+        let address = format!("{host}:{}", config.port);
+        thread::spawn(move || {
+            let mut failing = false;
+
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        println!("Connected to the MQTT broker at {address}.");
+                        failing = false;
+                        let _ = status_client.try_publish(&status_topic, qos, true, "online");
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        // only report the first failure of an outage.
+                        if !failing {
+                            println!("Warning: MQTT connection to {address} failed: {error}");
+                        }
+
+                        failing = true;
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+
+        Some(MqttPublisher {
+            config: config.clone(),
+            meter: meter.to_string(),
+            client,
+        })
+    }
+
+    /// Publishes readings until the sender is dropped.
+    pub fn enter(self, readings: Receiver<StoredReading>) {
+        let mut last_published = None;
+        let mut discovered = None;
+
+        for StoredReading { timestamp, reading, .. } in readings {
+            // the sensors depend on the fields and units the meter reports, which rarely change.
+            let discovery = (reading.units(), fields(&reading).map(|field| field.value.is_some()));
+            if self.config.discovery && discovered.as_ref() != Some(&discovery) {
+                self.publish_discovery(&reading);
+                discovered = Some(discovery);
+            }
+
+            if last_published.is_some_and(|last| timestamp - last < self.config.interval) {
+                continue;
+            }
+
+            last_published = Some(timestamp);
+            self.publish(&reading);
+        }
+    }
+
+    fn publish(&self, reading: &MeterReading) {
+        for field in fields(reading) {
+            if let Some(value) = field.value {
+                self.try_publish(self.field_topic(field.name), value);
+            }
+        }
+
+        if let Some(json_topic) = &self.config.json_topic {
+            self.try_publish(json_topic.replace("{meter}", &self.meter), serde_json::to_string(reading).unwrap());
+        }
+    }
+
+    /// Announces a sensor for each field of the reading, see https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery.
+    ///
+    /// The discovery messages are always retained, so Home Assistant finds them after restarting.
+    fn publish_discovery(&self, reading: &MeterReading) {
+        let node_id = format!("rusty-power-meter-{}", self.meter)
+            .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_");
+
+        let device = json!({
+            "identifiers": [node_id],
+            "name": format!("Power meter {}", self.meter),
+            "manufacturer": "rusty-power-meter",
+            "model": "SML",
+        });
+
+        for field in fields(reading) {
+            if field.value.is_none() {
+                continue;
+            }
+
+            // a `MeterReading` only holds the energy and the power of the lines, so there are no other device classes.
+            let (device_class, state_class) = match field.unit {
+                Some(Unit::WattHour) => (Some("energy"), "total_increasing"),
+                Some(Unit::Watt) => (Some("power"), "measurement"),
+                _ => (None, "measurement"),
+            };
+
+            let config = json!({
+                "name": field.label,
+                "unique_id": format!("{node_id}_{}", field.name),
+                "object_id": format!("{node_id}_{}", field.name),
+                "state_topic": self.field_topic(field.name),
+                "unit_of_measurement": field.unit.as_ref().map(Unit::as_str),
+                "device_class": device_class,
+                "state_class": state_class,
+                "availability_topic": self.config.status_topic.replace("{meter}", &self.meter),
+                "device": device,
+            });
+
+            let topic = format!("{}/sensor/{node_id}/{}/config", self.config.discovery_prefix, field.name);
+            let _ = self.client.try_publish(topic, self.config.qos(), true, config.to_string());
+        }
+    }
+
+    fn field_topic(&self, field: &str) -> String {
+        self.config.topic.replace("{meter}", &self.meter).replace("{field}", field)
+    }
+
+    /// Drops the message if the queue is full, a later reading supersedes it anyway.
+    fn try_publish(&self, topic: String, payload: String) {
+        let _ = self.client.try_publish(topic, self.config.qos(), self.config.retain, payload);
+    }
+}
+
+/// A value published to its own topic.
+struct Field {
+    name: &'static str,
+    /// The name of its sensor in Home Assistant.
+    label: &'static str,
+    value: Option<String>,
+    unit: Option<Unit>,
+}
+
+fn fields(reading: &MeterReading) -> [Field; 6] {
+    let power_unit = reading.line_one_unit.clone()
+        .or_else(|| reading.line_two_unit.clone())
+        .or_else(|| reading.line_three_unit.clone());
+    let total_power = reading.total_power();
+
+    [
+        Field {
+            name: "meter_reading",
+            label: "Energy",
+            value: reading.meter_reading.map(|value| value.to_string()),
+            unit: reading.meter_reading_unit.clone(),
+        },
+        Field {
+            name: "line_one",
+            label: "Power L1",
+            value: reading.line_one.map(|value| value.to_string()),
+            unit: reading.line_one_unit.clone(),
+        },
+        Field {
+            name: "line_two",
+            label: "Power L2",
+            value: reading.line_two.map(|value| value.to_string()),
+            unit: reading.line_two_unit.clone(),
+        },
+        Field {
+            name: "line_three",
+            label: "Power L3",
+            value: reading.line_three.map(|value| value.to_string()),
+            unit: reading.line_three_unit.clone(),
+        },
+        Field {
+            name: "total_power",
+            label: "Power",
+            value: total_power.map(|value| value.to_string()),
+            unit: power_unit.clone(),
+        },
+        Field {
+            name: "feed_in",
+            label: "Feed-in power",
+            value: total_power.map(|value| (-value).max(0).to_string()),
+            unit: power_unit,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{self, Receiver};
+
+    use super::*;
+
+    /// A message received by the stand-in broker.
+    struct Message {
+        topic: String,
+        payload: String,
+        retain: bool,
+    }
+
+    /// Starts a local MQTT broker which accepts a single client and passes on what it publishes. Returns its port.
+    fn broker() -> (u16, Receiver<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            while let Some((header, body)) = read_packet(&mut stream) {
+                match header >> 4 {
+                    // CONNECT, answered by CONNACK
+                    1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap(),
+                    // PUBLISH, QoS 0 without a packet identifier
+                    3 => {
+                        let length = u16::from_be_bytes([body[0], body[1]]) as usize;
+                        let message = Message {
+                            topic: String::from_utf8(body[2..2 + length].to_vec()).unwrap(),
+                            payload: String::from_utf8(body[2 + length..].to_vec()).unwrap(),
+                            retain: header & 1 == 1,
+                        };
+
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    // PINGREQ, answered by PINGRESP
+                    12 => stream.write_all(&[0xd0, 0x00]).unwrap(),
+                    _ => {}
+                }
+            }
+        });
+
+        (port, receiver)
+    }
+
+    /// Reads the first byte of the fixed header and the rest of a packet.
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0];
+        stream.read_exact(&mut header).ok()?;
+
+        let mut length = 0;
+        for shift in (0..28).step_by(7) {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).ok()?;
+            length |= ((byte[0] & 0x7f) as usize) << shift;
+
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).ok()?;
+
+        Some((header[0], body))
+    }
+
+    /// Waits for the messages published so far.
+    fn received(messages: &Receiver<Message>) -> Vec<Message> {
+        let mut received = vec![messages.recv_timeout(Duration::from_secs(5)).unwrap()];
+        while let Ok(message) = messages.recv_timeout(Duration::from_millis(500)) {
+            received.push(message);
+        }
+
+        received
+    }
+
+    fn config(port: u16) -> MqttConfig {
+        MqttConfig {
+            host: Some("127.0.0.1".to_string()),
+            port,
+            topic: "meters/{meter}/{field}".to_string(),
+            retain: false,
+            ..Default::default()
+        }
+    }
+
+    fn reading(timestamp: i64, line_one: i32) -> StoredReading {
+        StoredReading {
+            timestamp,
+            meter: "garage".to_string(),
+            reading: MeterReading { line_one: Some(line_one), line_one_unit: Some(Unit::Watt), ..Default::default() },
+        }
+    }
+
+    #[test]
+    fn lists_the_fields_of_a_reading() {
+        let reading = MeterReading {
+            meter_reading: Some(1234.5),
+            meter_reading_unit: Some(Unit::WattHour),
+            line_one: Some(100),
+            line_two: Some(-300),
+            line_two_unit: Some(Unit::Watt),
+            ..Default::default()
+        };
+
+        let fields = fields(&reading).map(|field| (field.name, field.value, field.unit));
+        assert_eq!(fields, [
+            ("meter_reading", Some("1234.5".to_string()), Some(Unit::WattHour)),
+            ("line_one", Some("100".to_string()), None),
+            ("line_two", Some("-300".to_string()), Some(Unit::Watt)),
+            ("line_three", None, None),
+            // the lines share the unit of the first line which has one.
+            ("total_power", Some("-200".to_string()), Some(Unit::Watt)),
+            ("feed_in", Some("200".to_string()), Some(Unit::Watt)),
+        ]);
+    }
+
+    #[test]
+    fn fills_in_the_topic_templates() {
+        let (port, messages) = broker();
+        let config = MqttConfig { topic: "{meter}/{field}/{meter}".to_string(), ..config(port) };
+        let publisher = MqttPublisher::connect(&config, "garage").unwrap();
+
+        assert_eq!(publisher.field_topic("line_one"), "garage/line_one/garage");
+
+        let (sender, readings) = mpsc::channel();
+        sender.send(reading(0, 100)).unwrap();
+        drop(sender);
+        publisher.enter(readings);
+
+        // the status is published once connected, after the readings queued until then.
+        let (status, messages) = received(&messages).into_iter().partition::<Vec<_>, _>(|message| message.topic.ends_with("/status"));
+        let topics = messages.iter().map(|message| message.topic.as_str()).collect::<Vec<_>>();
+        assert_eq!(topics, ["garage/line_one/garage", "garage/total_power/garage", "garage/feed_in/garage"]);
+        assert!(messages.iter().all(|message| !message.retain));
+
+        assert_eq!(status[0].topic, "rusty-power-meter/garage/status");
+        assert_eq!(status[0].payload, "online");
+        assert!(status[0].retain);
+    }
+
+    #[test]
+    fn publishes_at_most_once_per_interval() {
+        let (port, messages) = broker();
+        let config = MqttConfig { interval: 10, ..config(port) };
+        let publisher = MqttPublisher::connect(&config, "garage").unwrap();
+
+        let (sender, readings) = mpsc::channel();
+        for (timestamp, power) in [(0, 100), (5, 200), (10, 300), (19, 400), (20, 500)] {
+            sender.send(reading(timestamp, power)).unwrap();
+        }
+        drop(sender);
+        publisher.enter(readings);
+
+        let payloads = received(&messages).into_iter()
+            .filter(|message| message.topic == "meters/garage/line_one")
+            .map(|message| message.payload)
+            .collect::<Vec<_>>();
+        assert_eq!(payloads, ["100", "300", "500"]);
+    }
+
+    #[test]
+    fn announces_energy_and_power_sensors() {
+        let (port, messages) = broker();
+        let config = MqttConfig { discovery: true, ..config(port) };
+        let publisher = MqttPublisher::connect(&config, "garage").unwrap();
+
+        let (sender, readings) = mpsc::channel();
+        let mut energy = reading(0, 100);
+        energy.reading.meter_reading = Some(1234.5);
+        energy.reading.meter_reading_unit = Some(Unit::WattHour);
+        sender.send(energy).unwrap();
+        drop(sender);
+        publisher.enter(readings);
+
+        let discovery = received(&messages).into_iter()
+            .filter(|message| message.topic.starts_with("homeassistant/"))
+            .map(|message| {
+                // retained regardless of `retain`, for Home Assistant to find them after restarting.
+                assert!(message.retain);
+                (message.topic, serde_json::from_str::<serde_json::Value>(&message.payload).unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        let topics = discovery.iter().map(|(topic, _)| topic.as_str()).collect::<Vec<_>>();
+        assert_eq!(topics, [
+            "homeassistant/sensor/rusty-power-meter-garage/meter_reading/config",
+            "homeassistant/sensor/rusty-power-meter-garage/line_one/config",
+            "homeassistant/sensor/rusty-power-meter-garage/total_power/config",
+            "homeassistant/sensor/rusty-power-meter-garage/feed_in/config",
+        ]);
+
+        let device = json!({
+            "identifiers": ["rusty-power-meter-garage"],
+            "name": "Power meter garage",
+            "manufacturer": "rusty-power-meter",
+            "model": "SML",
+        });
+        assert_eq!(discovery[0].1, json!({
+            "name": "Energy",
+            "unique_id": "rusty-power-meter-garage_meter_reading",
+            "object_id": "rusty-power-meter-garage_meter_reading",
+            "state_topic": "meters/garage/meter_reading",
+            "unit_of_measurement": "Wh",
+            "device_class": "energy",
+            "state_class": "total_increasing",
+            "availability_topic": "rusty-power-meter/garage/status",
+            "device": device,
+        }));
+        assert_eq!(discovery[1].1, json!({
+            "name": "Power L1",
+            "unique_id": "rusty-power-meter-garage_line_one",
+            "object_id": "rusty-power-meter-garage_line_one",
+            "state_topic": "meters/garage/line_one",
+            "unit_of_measurement": "W",
+            "device_class": "power",
+            "state_class": "measurement",
+            "availability_topic": "rusty-power-meter/garage/status",
+            "device": device,
+        }));
+    }
+}
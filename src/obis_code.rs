@@ -7,7 +7,7 @@ use sml_rs::parser::OctetStr;
 ///
 /// [obis]: https://de.wikipedia.org/wiki/OBIS-Kennzahlen
 /// [obiscode]: https://onemeter.com/docs/device/obis/
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ObisCode {
     inner: [u8; 5],
 }
@@ -22,12 +22,6 @@ impl Display for ObisCode {
     }
 }
 
-impl Default for ObisCode {
-    fn default() -> Self {
-        Self { inner: [0; 5] }
-    }
-}
-
 impl ObisCode {
     /// Parses an OBIS code from a string such as `&[1, 2, 3, 4, 5, 255]`.
     ///
@@ -65,6 +59,7 @@ impl ObisCode {
     /// const OBIS_CODE: ObisCode = ObisCode::from_str("1-2:3.4.5");
     /// assert_eq!(&format!("{OBIS_CODE}"), "1-2:3.4.5");
     /// ```
+    #[allow(dead_code)]
     pub const fn from_str(s: &'static str) -> Self {
         match Self::try_from_str(s) {
             Ok(x) => x,
@@ -73,6 +68,7 @@ impl ObisCode {
     }
 
     /// Views this Obis code as a slice of bytes.
+    #[allow(dead_code)]
     pub const fn as_bytes(&self) -> &[u8; 5] {
         &self.inner
     }
@@ -136,6 +132,7 @@ pub enum ObisParseError {
     /// Provided octet string has invalid length
     InvalidLength,
     /// Provided octet string's last byte doesn't equal 255
+    #[allow(dead_code)]
     InvalidLastByte,
 }
 
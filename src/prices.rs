@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Error};
+use serde::Serialize;
+
+/// The price per kWh of a dynamic tariff, valid from `timestamp` for `duration` seconds.
+#[derive(Clone, Serialize)]
+pub struct SpotPrice {
+    pub timestamp: i64,
+    pub duration: i64,
+    pub price: f64,
+}
+
+/// Spot prices indexed by their start, for looking up the price at any point in time.
+#[derive(Default)]
+pub struct SpotPrices(BTreeMap<i64, SpotPrice>);
+
+impl SpotPrices {
+    pub fn new(prices: Vec<SpotPrice>) -> Self {
+        SpotPrices(prices.into_iter().map(|price| (price.timestamp, price)).collect())
+    }
+
+    /// Returns the price valid at `timestamp`, if there is one.
+    pub fn at(&self, timestamp: i64) -> Option<f64> {
+        self.0.range(..=timestamp)
+            .next_back()
+            .filter(|(_, price)| timestamp < price.timestamp + price.duration)
+            .map(|(_, price)| price.price)
+    }
+}
+
+/// The cheapest time found by `cheapest`.
+#[derive(Serialize)]
+pub struct CheapestPrices {
+    pub from: i64,
+    pub to: i64,
+    pub average_price: f64,
+    pub prices: Vec<SpotPrice>,
+}
+
+/// Finds the cheapest `hours` among `prices`, which have to be ordered by time.
+///
+/// With `consecutive` the hours form a single block, e.g. for running a dishwasher,
+/// otherwise the cheapest individual prices are picked, e.g. for charging a car.
+pub fn cheapest(prices: &[SpotPrice], hours: f64, consecutive: bool) -> Result<Option<CheapestPrices>, Error> {
+    if !hours.is_finite() || hours <= 0.0 {
+        bail!("The number of hours has to be positive.");
+    }
+
+    let Some(first) = prices.first() else {
+        return Ok(None);
+    };
+
+    if first.duration <= 0 {
+        bail!("Invalid price duration {}.", first.duration);
+    }
+
+    // prices are assumed to have the same resolution, e.g. hourly or quarter-hourly.
+    // the count is checked before the conversion, as huge values would saturate.
+    let count = (hours * 3600.0 / first.duration as f64).ceil();
+    if count > prices.len() as f64 {
+        return Ok(None);
+    }
+
+    let count = count as usize;
+    if count < 1 {
+        bail!("The number of hours has to be positive.");
+    }
+
+    let selected = if consecutive {
+        let mut best: Option<(f64, &[SpotPrice])> = None;
+
+        for window in prices.windows(count) {
+            let contiguous = window.windows(2).all(|pair| pair[0].timestamp + pair[0].duration == pair[1].timestamp);
+            let sum = window.iter().map(|price| price.price).sum::<f64>();
+
+            if contiguous && best.is_none_or(|(best_sum, _)| sum < best_sum) {
+                best = Some((sum, window));
+            }
+        }
+
+        match best {
+            Some((_, window)) => window.to_vec(),
+            None => return Ok(None),
+        }
+    } else {
+        let mut selected = prices.to_vec();
+        selected.sort_by(|a, b| a.price.total_cmp(&b.price));
+        selected.truncate(count);
+        selected.sort_by_key(|price| price.timestamp);
+        selected
+    };
+
+    let last = selected.last().unwrap();
+    Ok(Some(CheapestPrices {
+        from: selected[0].timestamp,
+        to: last.timestamp + last.duration,
+        average_price: selected.iter().map(|price| price.price).sum::<f64>() / selected.len() as f64,
+        prices: selected,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prices starting at `start`, each valid for `duration` seconds right after the previous one.
+    fn prices(start: i64, duration: i64, prices: &[f64]) -> Vec<SpotPrice> {
+        prices.iter()
+            .enumerate()
+            .map(|(index, &price)| SpotPrice { timestamp: start + index as i64 * duration, duration, price })
+            .collect()
+    }
+
+    fn starts(cheapest: &CheapestPrices) -> Vec<i64> {
+        cheapest.prices.iter().map(|price| price.timestamp).collect()
+    }
+
+    #[test]
+    fn picks_a_block_or_the_cheapest_individual_prices() {
+        let prices = prices(0, 3600, &[0.3, 0.1, 0.5, 0.2, 0.05]);
+
+        let block = cheapest(&prices, 2.0, true).unwrap().unwrap();
+        assert_eq!(starts(&block), [3 * 3600, 4 * 3600]);
+        assert_eq!((block.from, block.to), (3 * 3600, 5 * 3600));
+        assert_eq!(block.average_price, 0.125);
+
+        let individual = cheapest(&prices, 2.0, false).unwrap().unwrap();
+        assert_eq!(starts(&individual), [3600, 4 * 3600]);
+        assert_eq!((individual.from, individual.to), (3600, 5 * 3600));
+        assert_eq!(individual.average_price, (0.1 + 0.05) / 2.0);
+    }
+
+    #[test]
+    fn rounds_up_to_whole_prices() {
+        let quarter_hours = prices(0, 900, &[0.3, 0.2, 0.2, 0.2, 0.2, 0.1, 0.3]);
+        assert_eq!(starts(&cheapest(&quarter_hours, 1.0, true).unwrap().unwrap()), [1800, 2700, 3600, 4500]);
+
+        let hours = prices(0, 3600, &[0.3, 0.2, 0.1]);
+        assert_eq!(starts(&cheapest(&hours, 1.5, true).unwrap().unwrap()), [3600, 7200]);
+        assert_eq!(starts(&cheapest(&hours, 0.1, true).unwrap().unwrap()), [7200]);
+    }
+
+    #[test]
+    fn prefers_the_earliest_of_equal_prices() {
+        let prices = prices(0, 3600, &[0.2, 0.1, 0.1, 0.2, 0.1, 0.1]);
+
+        assert_eq!(starts(&cheapest(&prices, 2.0, true).unwrap().unwrap()), [3600, 7200]);
+        assert_eq!(starts(&cheapest(&prices, 3.0, false).unwrap().unwrap()), [3600, 7200, 4 * 3600]);
+    }
+
+    #[test]
+    fn keeps_a_block_within_contiguous_prices() {
+        // the cheapest two prices are a day apart.
+        let mut prices = prices(0, 3600, &[0.4, 0.1]);
+        prices.extend(self::prices(86_400, 3600, &[0.1, 0.3]));
+
+        assert_eq!(starts(&cheapest(&prices, 2.0, true).unwrap().unwrap()), [86_400, 90_000]);
+        assert_eq!(starts(&cheapest(&prices, 2.0, false).unwrap().unwrap()), [3600, 86_400]);
+        assert!(cheapest(&prices, 3.0, true).unwrap().is_none());
+        assert_eq!(cheapest(&prices, 3.0, false).unwrap().unwrap().prices.len(), 3);
+    }
+
+    #[test]
+    fn finds_nothing_without_enough_prices() {
+        let prices = prices(0, 3600, &[0.3, 0.2]);
+
+        assert!(cheapest(&[], 1.0, true).unwrap().is_none());
+        assert!(cheapest(&prices, 2.5, true).unwrap().is_none());
+        assert!(cheapest(&prices, 2.5, false).unwrap().is_none());
+        assert!(cheapest(&prices, 1e300, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_hours_and_durations() {
+        let hourly = prices(0, 3600, &[0.3, 0.2]);
+
+        for hours in [0.0, -1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(cheapest(&hourly, hours, true).err().unwrap().to_string(), "The number of hours has to be positive.", "{hours}");
+        }
+
+        // rounds down to no price at all.
+        assert!(cheapest(&prices(0, i64::MAX, &[0.3]), 1e-320, true).is_err());
+        assert_eq!(cheapest(&prices(0, 0, &[0.3]), 1.0, true).err().unwrap().to_string(), "Invalid price duration 0.");
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use axum::response::Response;
+use chrono::NaiveTime;
+use serde::Deserialize;
+
+use crate::base_load::{base_load, BaseLoadOptions, BaseLoadReport};
+use crate::server::api::json_response;
+use crate::storage::ReadingStore;
+use crate::timestamp::{Interval, TimeRange};
+
+#[derive(Deserialize)]
+pub struct BaseLoadParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    period: Option<Interval>,
+    night_from: Option<NaiveTime>,
+    night_to: Option<NaiveTime>,
+    percentile: Option<f64>,
+}
+
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, params: BaseLoadParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<BaseLoadReport, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        let meter = params.meter.as_deref().unwrap_or("default");
+
+        let defaults = BaseLoadOptions::default();
+        let options = BaseLoadOptions {
+            night_from: params.night_from.unwrap_or(defaults.night_from),
+            night_to: params.night_to.unwrap_or(defaults.night_to),
+            percentile: params.percentile.unwrap_or(defaults.percentile),
+        };
+
+        base_load(store.as_ref(), &range, meter, params.period.unwrap_or(Interval::Day), options)
+    }).await.unwrap();
+
+    json_response(result)
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::consumption::{consumption, ConsumptionReport};
+use crate::server::api::json_response;
+use crate::storage::ReadingStore;
+use crate::timestamp::{Interval, TimeRange};
+
+#[derive(Deserialize)]
+pub struct ConsumptionParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    interval: Option<Interval>,
+}
+
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, params: ConsumptionParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<ConsumptionReport, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        let meter = params.meter.as_deref().unwrap_or("default");
+
+        consumption(store.as_ref(), &range, meter, params.interval.unwrap_or(Interval::Hour))
+    }).await.unwrap();
+
+    json_response(result)
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::cost::{cost, CostPeriod, CostReport};
+use crate::database::ReadonlyDatabase;
+use crate::prices::SpotPrices;
+use crate::server::api::json_response;
+use crate::storage::ReadingStore;
+use crate::tariff::Tariff;
+use crate::timestamp::TimeRange;
+
+#[derive(Deserialize)]
+pub struct CostParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    period: Option<CostPeriod>,
+}
+
+pub async fn handler(
+    store: Arc<dyn ReadingStore + Send + Sync>,
+    database: Option<Arc<ReadonlyDatabase>>,
+    config: Arc<Config>,
+    params: CostParams,
+) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<CostReport, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        let meter = params.meter.as_deref().unwrap_or("default");
+
+        let prices = match database {
+            Some(database) => SpotPrices::new(database.prices(&range)?),
+            None => SpotPrices::default(),
+        };
+
+        // without a tariff, the spot prices are all there is.
+        let spot_only;
+        let tariff = match &config.tariff {
+            Some(tariff) => tariff,
+            None => {
+                spot_only = Tariff::spot_only();
+                &spot_only
+            }
+        };
+
+        cost(store.as_ref(), tariff, &prices, &range, meter, params.period.unwrap_or(CostPeriod::Month))
+    }).await.unwrap();
+
+    json_response(result)
+}
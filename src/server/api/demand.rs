@@ -0,0 +1,44 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::demand::{monthly_peaks, DemandConfig, DemandReport, LiveDemand};
+use crate::server::api::json_response;
+use crate::storage::ReadingStore;
+use crate::timestamp::TimeRange;
+
+#[derive(Deserialize)]
+pub struct DemandParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    /// Window length in seconds, defaults to the configured one.
+    window: Option<i64>,
+    sliding: Option<bool>,
+}
+
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, config: Arc<Config>, params: DemandParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<DemandReport, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        let meter = params.meter.as_deref().unwrap_or("default");
+
+        let demand = DemandConfig {
+            window: params.window.unwrap_or(config.demand.window),
+            sliding: params.sliding.unwrap_or(config.demand.sliding),
+            ..config.demand
+        };
+
+        monthly_peaks(store.as_ref(), &range, meter, &demand)
+    }).await.unwrap();
+
+    json_response(result)
+}
+
+pub async fn now_handler(live_demand: Arc<Mutex<LiveDemand>>) -> Response {
+    let live_demand = live_demand.lock().unwrap().clone();
+
+    json_response(Ok(live_demand))
+}
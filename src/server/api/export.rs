@@ -0,0 +1,69 @@
+use std::io;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::header;
+use axum::response::Response;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::export::{export, ExportFormat, Rollup};
+use crate::server::api::error_response;
+use crate::storage::ReadingStore;
+use crate::timestamp::TimeRange;
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    format: Option<ExportFormat>,
+    rollup: Option<Rollup>,
+}
+
+/// Forwards everything written to it as chunks of the response body.
+struct ChannelWriter(mpsc::Sender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Client disconnected."))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, params: ExportParams) -> Response {
+    let range = match TimeRange::parse(params.from.as_deref(), params.to.as_deref()) {
+        Ok(range) => range,
+        Err(error) => {
+            return error_response(400, &error.to_string())
+        }
+    };
+
+    let format = params.format.unwrap_or(ExportFormat::Csv);
+    let (sender, receiver) = mpsc::channel(16);
+
+    // the export runs on a blocking thread and streams its output, so the whole range is never held in memory.
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::new(ChannelWriter(sender.clone()));
+
+        if let Err(error) = export(store.as_ref(), &range, params.meter.as_deref(), params.rollup, format, writer) {
+            // aborts the response, as the status has already been sent.
+            let _ = sender.blocking_send(Err(io::Error::other(error.to_string())));
+        }
+    });
+
+    Response::builder()
+        .status(200)
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"readings.{}\"", format.extension()))
+        .body(Body::from_stream(ReceiverStream::new(receiver)))
+        .unwrap()
+}
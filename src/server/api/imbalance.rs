@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::imbalance::{imbalance, ImbalanceReport, DEFAULT_THRESHOLD};
+use crate::server::api::json_response;
+use crate::storage::ReadingStore;
+use crate::timestamp::{Interval, TimeRange};
+
+#[derive(Deserialize)]
+pub struct ImbalanceParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    bucket: Option<Interval>,
+    /// Asymmetry in W above which the time is counted.
+    threshold: Option<f64>,
+}
+
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, params: ImbalanceParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<ImbalanceReport, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        let meter = params.meter.as_deref().unwrap_or("default");
+
+        imbalance(store.as_ref(), &range, meter, params.bucket, params.threshold.unwrap_or(DEFAULT_THRESHOLD))
+    }).await.unwrap();
+
+    json_response(result)
+}
@@ -1,2 +1,39 @@
+pub mod base_load;
+pub mod consumption;
+pub mod cost;
+pub mod demand;
+pub mod export;
+pub mod imbalance;
 pub mod now;
-pub mod query;
\ No newline at end of file
+pub mod prices;
+pub mod query;
+pub mod readings;
+pub mod recent;
+pub mod stats;
+pub mod stream;
+
+use anyhow::Error;
+use axum::http::header;
+use axum::response::Response;
+use serde::Serialize;
+
+/// Responds with `result` as JSON, or with its error and status 400.
+fn json_response(result: Result<impl Serialize, Error>) -> Response {
+    match result {
+        Ok(value) => Response::builder()
+            .status(200)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&value).unwrap().into())
+            .unwrap(),
+        Err(error) => error_response(400, &error.to_string()),
+    }
+}
+
+/// Responds with `{"error": <message>}`.
+fn error_response(status: u16, message: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .unwrap()
+}
@@ -1,22 +1,59 @@
 use std::sync::Arc;
-use axum::http::header;
+use axum::body::Body;
+use axum::http::{header, HeaderMap};
 use axum::response::Response;
-use crossbeam_utils::atomic::AtomicCell;
-use crate::meter_reading::MeterReading;
+use serde::Deserialize;
+use crate::latest_reading::LatestReading;
+use crate::storage::StoredReading;
 
-pub async fn handler(latest_reading_cell: Arc<AtomicCell<Option<MeterReading>>>) -> Response {
-    let reading = latest_reading_cell.take();
+#[derive(Deserialize)]
+pub struct NowParams {
+    /// Sequence number of the reading the client already has, to wait for the next one.
+    after: Option<u64>,
+}
 
-    let status = if reading.is_some() { 200 } else { 204 };
+pub async fn handler(latest_reading: Arc<LatestReading>, headers: HeaderMap, params: NowParams) -> Response {
+    respond(&latest_reading, &headers, params, "application/json", |latest| serde_json::to_string(&latest.reading).unwrap()).await
+}
 
-    let body = match reading {
-        Some(reading) => serde_json::to_string(&reading).unwrap(),
-        None => "".to_string()
+/// Responds with the latest reading, its run and sequence number as `ETag` and the seconds since it was received as `Age`.
+///
+/// With `after` the response waits for a newer reading and is `204 No Content` if none arrives in time.
+pub async fn respond(
+    latest_reading: &LatestReading,
+    headers: &HeaderMap,
+    params: NowParams,
+    content_type: &str,
+    body: impl Fn(&StoredReading) -> String,
+) -> Response {
+    let latest = match params.after {
+        Some(after) => latest_reading.newer_than(after).await,
+        None => latest_reading.get(),
     };
 
-    Response::builder()
-        .status(status)
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(body.into())
+    let Some(latest) = latest else {
+        return Response::builder()
+            .status(204)
+            .body(Body::empty())
+            .unwrap();
+    };
+
+    let etag = latest.etag();
+    let response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::AGE, latest.received.elapsed().as_secs())
+        .header(header::CACHE_CONTROL, "no-cache");
+
+    if headers.get(header::IF_NONE_MATCH).is_some_and(|value| value == etag.as_str()) {
+        return response
+            .status(304)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    response
+        .status(200)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body(&latest.reading).into())
         .unwrap()
-}
\ No newline at end of file
+}
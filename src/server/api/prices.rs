@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{bail, Error};
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::database::ReadonlyDatabase;
+use crate::prices::{cheapest, CheapestPrices, SpotPrice};
+use crate::server::api::{error_response, json_response};
+use crate::timestamp::TimeRange;
+
+#[derive(Deserialize)]
+pub struct PricesParams {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CheapestParams {
+    /// How many hours are needed.
+    hours: f64,
+    /// How many hours from now to look ahead, 24 by default.
+    within: Option<f64>,
+    /// Whether the hours have to be one block, true by default.
+    consecutive: Option<bool>,
+}
+
+pub async fn handler(database: Arc<ReadonlyDatabase>, params: PricesParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<Vec<SpotPrice>, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        database.prices(&range)
+    }).await.unwrap();
+
+    json_response(result)
+}
+
+/// Looks up the cheapest upcoming hours, e.g. to plan when to run the dishwasher.
+pub async fn cheapest_handler(database: Arc<ReadonlyDatabase>, params: CheapestParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<Option<CheapestPrices>, Error> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
+        let range = look_ahead(now, params.within)?;
+
+        // the price currently valid counts as upcoming, as there's still time left of it.
+        let prices = database.prices(&range)?;
+        cheapest(&prices, params.hours, params.consecutive.unwrap_or(true))
+    }).await.unwrap();
+
+    match result {
+        Ok(None) => error_response(404, "Not enough upcoming prices."),
+        result => json_response(result),
+    }
+}
+
+/// The range from `now` to `within` hours later, 24 by default.
+fn look_ahead(now: i64, within: Option<f64>) -> Result<TimeRange, Error> {
+    let within = within.unwrap_or(24.0);
+    if !within.is_finite() || within <= 0.0 {
+        bail!("The number of hours to look ahead has to be positive.");
+    }
+
+    // huge values saturate and then overflow the end of the range.
+    let Some(to) = now.checked_add((within * 3600.0) as i64) else {
+        bail!("The number of hours to look ahead is too large.");
+    };
+
+    TimeRange::new(Some(now), Some(to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_ahead_a_positive_number_of_hours() {
+        let range = look_ahead(1000, None).unwrap();
+        assert_eq!((range.from, range.to), (1000, 1000 + 24 * 3600));
+
+        let range = look_ahead(1000, Some(1.5)).unwrap();
+        assert_eq!((range.from, range.to), (1000, 1000 + 5400));
+
+        for within in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(look_ahead(1000, Some(within)).unwrap_err().to_string(), "The number of hours to look ahead has to be positive.");
+        }
+        assert_eq!(look_ahead(1000, Some(1e300)).unwrap_err().to_string(), "The number of hours to look ahead is too large.");
+    }
+}
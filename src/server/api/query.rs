@@ -1,29 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use axum::http::header;
 use axum::response::Response;
-use serde::Serialize;
 use crate::database::ReadonlyDatabase;
+use crate::server::api::json_response;
 
+/// Sets the flag when dropped, which happens to the request when the client disconnects.
+struct CancelOnDrop(Arc<AtomicBool>);
 
-
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
 
 pub async fn handler(database: Arc<ReadonlyDatabase>, body: String) -> Response {
-    let result = database.query(&body);
-    match result {
-        Ok(query_result) => {
-            let json = serde_json::to_string(&query_result).unwrap();
-            
-            Response::builder()
-                .status(200)
-                .header(header::CONTENT_TYPE, "application/json")
-                .body(json.into())
-                .unwrap()
-        }
-        Err(error) => {
-            Response::builder()
-                .status(400)
-                .body(format!("{{\"error\": \"{}\"}}", error.to_string()).into())
-                .unwrap()
-        }
-    }
-}
\ No newline at end of file
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let _cancel_on_drop = CancelOnDrop(cancelled.clone());
+
+    // the query runs on a blocking thread, so a slow one doesn't hold up other requests.
+    let result = tokio::task::spawn_blocking(move || database.query(&body, &cancelled)).await.unwrap();
+
+    json_response(result)
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Error};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::meter_reading::MeterReading;
+use crate::server::api::json_response;
+use crate::storage::{Order, ReadingQuery, ReadingStore};
+use crate::timestamp::TimeRange;
+use crate::unit::Unit;
+
+/// Readings per page unless `limit` says otherwise.
+const DEFAULT_LIMIT: u64 = 1000;
+
+const MAX_LIMIT: u64 = 10_000;
+
+const FIELDS: [&str; 6] = ["meter_time", "meter_reading", "line_one", "line_two", "line_three", "total_power"];
+
+#[derive(Deserialize)]
+pub struct ReadingsParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    /// Comma separated, all fields if missing.
+    fields: Option<String>,
+    limit: Option<u64>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    order: Option<Order>,
+}
+
+#[derive(Serialize)]
+pub struct ReadingsPage {
+    meter: String,
+    /// The unit of each selected field which has one.
+    units: BTreeMap<&'static str, &'static str>,
+    readings: Vec<Map<String, Value>>,
+    /// Pass as `cursor` to get the next page, `null` on the last page.
+    next_cursor: Option<String>,
+}
+
+/// Returns a page of stored readings with the selected fields.
+///
+/// A page only holds readings of one meter, whose timestamps are unique. So the cursor is the timestamp
+/// of the last reading of the page and the next page starts right after it using the timestamp index.
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, params: ReadingsParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<ReadingsPage, Error> {
+        let meter = params.meter.unwrap_or_else(|| "default".to_string());
+        let order = params.order.unwrap_or_default();
+
+        let fields = match &params.fields {
+            Some(fields) => fields.split(',')
+                .map(|field| FIELDS.iter().find(|name| **name == field).copied().ok_or_else(|| anyhow!("Unknown field \"{field}\".")))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => FIELDS.to_vec(),
+        };
+
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit == 0 || limit > MAX_LIMIT {
+            bail!("The limit has to be between 1 and {MAX_LIMIT}.");
+        }
+
+        let mut range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        if let Some(cursor) = &params.cursor {
+            let cursor = cursor.parse::<i64>().map_err(|_| anyhow!("Invalid cursor \"{cursor}\"."))?;
+
+            // a cursor outside of the range leaves it empty.
+            match order {
+                Order::Asc => range.from = range.from.max(cursor.saturating_add(1)).min(range.to),
+                Order::Desc => range.to = range.to.min(cursor).max(range.from),
+            }
+        }
+
+        let mut query = ReadingQuery::new(range);
+        query.meter = Some(meter.clone());
+        // one more than asked for tells whether there is another page.
+        query.limit = Some(limit + 1);
+        query.order = order;
+
+        let mut readings = store.readings(&query)?.collect::<Result<Vec<_>, _>>()?;
+
+        let next_cursor = if readings.len() as u64 > limit {
+            readings.truncate(limit as usize);
+            readings.last().map(|reading| reading.timestamp.to_string())
+        } else {
+            None
+        };
+
+        let mut units = BTreeMap::new();
+        let readings = readings.iter()
+            .map(|stored| {
+                let mut values = Map::new();
+                values.insert("timestamp".to_string(), stored.timestamp.into());
+
+                for field in &fields {
+                    let (value, unit) = field_value(&stored.reading, field);
+                    if let Some(unit) = unit {
+                        units.entry(*field).or_insert(unit.as_str());
+                    }
+
+                    values.insert(field.to_string(), value);
+                }
+
+                values
+            })
+            .collect();
+
+        Ok(ReadingsPage { meter, units, readings, next_cursor })
+    }).await.unwrap();
+
+    json_response(result)
+}
+
+fn field_value(reading: &MeterReading, field: &str) -> (Value, Option<Unit>) {
+    let power_unit = || reading.line_one_unit.clone()
+        .or_else(|| reading.line_two_unit.clone())
+        .or_else(|| reading.line_three_unit.clone());
+
+    match field {
+        "meter_time" => (reading.meter_time.into(), None),
+        "meter_reading" => (reading.meter_reading.into(), reading.meter_reading_unit.clone()),
+        "line_one" => (reading.line_one.into(), reading.line_one_unit.clone()),
+        "line_two" => (reading.line_two.into(), reading.line_two_unit.clone()),
+        "line_three" => (reading.line_three.into(), reading.line_three_unit.clone()),
+        "total_power" => (reading.total_power().into(), power_unit()),
+        _ => (Value::Null, None),
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::latest_reading::RecentReadings;
+use crate::server::api::{error_response, json_response};
+
+#[derive(Deserialize)]
+pub struct RecentParams {
+    /// Defaults to 300, at most the minutes kept in memory (`--recent-minutes`).
+    seconds: Option<i64>,
+    meter: Option<String>,
+}
+
+/// Returns every reading of the last seconds from memory, including those not written to the store yet.
+pub async fn handler(recent_readings: Arc<RecentReadings>, params: RecentParams) -> Response {
+    let seconds = params.seconds.unwrap_or(300);
+    if seconds < 0 {
+        return error_response(400, "The seconds can't be negative.");
+    }
+
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as i64);
+    let from = now - seconds.min(recent_readings.retention());
+    let readings = recent_readings.since(from, params.meter.as_deref());
+
+    json_response(Ok(readings))
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use axum::response::Response;
+use serde::Deserialize;
+
+use crate::server::api::json_response;
+use crate::stats::{power_stats, StatsReport};
+use crate::storage::ReadingStore;
+use crate::timestamp::{Interval, TimeRange};
+
+#[derive(Deserialize)]
+pub struct StatsParams {
+    from: Option<String>,
+    to: Option<String>,
+    meter: Option<String>,
+    bucket: Option<Interval>,
+}
+
+pub async fn handler(store: Arc<dyn ReadingStore + Send + Sync>, params: StatsParams) -> Response {
+    let result = tokio::task::spawn_blocking(move || -> Result<StatsReport, Error> {
+        let range = TimeRange::parse(params.from.as_deref(), params.to.as_deref())?;
+        let meter = params.meter.as_deref().unwrap_or("default");
+
+        power_stats(store.as_ref(), &range, meter, params.bucket)
+    }).await.unwrap();
+
+    json_response(result)
+}
@@ -0,0 +1,73 @@
+use std::convert::Infallible;
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+
+use crate::storage::StoredReading;
+
+#[derive(Deserialize)]
+pub struct StreamParams {
+    meter: Option<String>,
+}
+
+/// Sends every new reading as a `reading` event with the reading, its timestamp and meter as JSON,
+/// and the timestamp as id.
+pub async fn handler(live_readings: broadcast::Sender<StoredReading>, params: StreamParams) -> Response {
+    let events = BroadcastStream::new(live_readings.subscribe())
+        .filter_map(move |reading| {
+            // a client too slow to keep up misses some readings instead of holding up the others.
+            let reading = reading.ok()?;
+
+            if params.meter.as_ref().is_some_and(|meter| *meter != reading.meter) {
+                return None;
+            }
+
+            let event = Event::default()
+                .event("reading")
+                .id(reading.timestamp.to_string())
+                .json_data(&reading)
+                .unwrap();
+
+            Some(Ok::<_, Infallible>(event))
+        });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meter_reading::MeterReading;
+
+    #[tokio::test]
+    async fn sends_the_readings_of_the_meter() {
+        let (live_readings, _) = broadcast::channel(16);
+        let response = handler(live_readings.clone(), StreamParams { meter: Some("grid".to_string()) }).await;
+
+        for (timestamp, meter) in [(100, "grid"), (101, "solar"), (102, "grid")] {
+            let reading = MeterReading { line_one: Some(-100), ..Default::default() };
+            live_readings.send(StoredReading { timestamp, meter: meter.to_string(), reading }).ok().unwrap();
+        }
+        drop(live_readings);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let events = String::from_utf8(body.to_vec()).unwrap();
+        let events = events.split_terminator("\n\n").collect::<Vec<_>>();
+
+        assert_eq!(events.len(), 2);
+        for (event, timestamp) in events.iter().zip([100, 102]) {
+            let mut lines = event.lines();
+            assert_eq!(lines.next(), Some("event: reading"));
+            assert_eq!(lines.next(), Some(format!("id: {timestamp}").as_str()));
+
+            let data: serde_json::Value = serde_json::from_str(lines.next().unwrap().strip_prefix("data: ").unwrap()).unwrap();
+            assert_eq!((data["timestamp"].as_i64(), data["meter"].as_str()), (Some(timestamp), Some("grid")));
+        }
+    }
+}
@@ -0,0 +1,501 @@
+"use strict";
+
+// the meter shown, `/?meter=garage` for another one.
+const meter = new URLSearchParams(location.search).get("meter") || "default";
+
+// full scale of a gauge, 20 A at 230 V.
+const GAUGE_MAX = 4600;
+
+// points of the history chart, the readings are averaged to about this many buckets.
+const CHART_POINTS = 600;
+
+const LINES = [
+    { key: "line_one", column: 1, label: "L1", color: "--line-one" },
+    { key: "line_two", column: 2, label: "L2", color: "--line-two" },
+    { key: "line_three", column: 3, label: "L3", color: "--line-three" },
+];
+
+const $ = (id) => document.getElementById(id);
+const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+const css = (name) => getComputedStyle(document.documentElement).getPropertyValue(name).trim();
+const now = () => Math.floor(Date.now() / 1000);
+const quote = (text) => "'" + String(text).replace(/'/g, "''") + "'";
+
+async function query(sql) {
+    const response = await fetch("/api/query", { method: "POST", body: sql });
+    const text = await response.text();
+
+    let result;
+    try {
+        result = JSON.parse(text);
+    } catch {
+        throw new Error(text || response.statusText);
+    }
+
+    if (!response.ok) {
+        throw new Error(result.error || response.statusText);
+    }
+
+    return result;
+}
+
+// live gauges
+
+let receivedAt = null;
+let energyUnit = "WattHour";
+
+function createGauges() {
+    for (const line of LINES) {
+        const figure = document.createElement("figure");
+        figure.className = "gauge";
+        figure.innerHTML = `
+            <svg viewBox="0 0 120 74">
+                <path class="track" d="M 10 64 A 50 50 0 0 1 110 64" pathLength="100"></path>
+                <path class="value" d="M 10 64 A 50 50 0 0 1 110 64" pathLength="100" stroke-dasharray="0 100"></path>
+                <text x="60" y="62">–</text>
+            </svg>
+            <figcaption>${line.label}</figcaption>`;
+
+        line.gauge = figure;
+        $("gauges").append(figure);
+    }
+}
+
+function showReading(reading) {
+    let total = null;
+
+    for (const line of LINES) {
+        const value = reading[line.key];
+        const arc = line.gauge.querySelector(".value");
+        const text = line.gauge.querySelector("text");
+
+        if (value === null || value === undefined) {
+            arc.setAttribute("stroke-dasharray", "0 100");
+            text.textContent = "–";
+            continue;
+        }
+
+        total = (total ?? 0) + value;
+
+        const share = Math.min(Math.abs(value) / GAUGE_MAX, 1) * 100;
+        arc.setAttribute("stroke-dasharray", `${share} 100`);
+        arc.style.stroke = css(value < 0 ? "--feed-in" : line.color);
+        text.textContent = `${value} W`;
+    }
+
+    $("total").textContent = total ?? "–";
+    $("direction").textContent = total === null ? "" : total < 0 ? "feeding in" : "drawing";
+
+    if (reading.meter_reading_unit) {
+        energyUnit = reading.meter_reading_unit;
+    }
+}
+
+function showAge() {
+    const status = $("status");
+    if (receivedAt === null) {
+        return;
+    }
+
+    const age = Math.max(0, Math.round((Date.now() - receivedAt) / 1000));
+    status.textContent = age < 2 ? "Live" : `Updated ${age} s ago`;
+    status.className = age < 30 ? "status live" : "status stale";
+}
+
+// waits for every new reading with `?after=`, so the gauges update as soon as the meter sends.
+async function pollReadings() {
+    let sequence = null;
+
+    for (;;) {
+        try {
+            const url = sequence === null ? "/api/now" : `/api/now?after=${sequence}`;
+            const response = await fetch(url, { cache: "no-store" });
+
+            if (response.status === 200) {
+                // the ETag is `"<run>-<sequence>"`.
+                sequence = Number(response.headers.get("ETag").replace(/"/g, "").split("-").pop());
+                receivedAt = Date.now() - Number(response.headers.get("Age") || 0) * 1000;
+                showReading(await response.json());
+                showAge();
+            } else if (sequence === null) {
+                // no reading yet.
+                $("status").textContent = "Waiting for the meter…";
+                await sleep(2000);
+            }
+            // otherwise nothing new arrived in time, so just wait again.
+        } catch {
+            $("status").textContent = "Offline";
+            $("status").className = "status stale";
+            await sleep(5000);
+        }
+    }
+}
+
+// today's consumption
+
+async function updateToday() {
+    const midnight = new Date();
+    midnight.setHours(0, 0, 0, 0);
+    const from = Math.floor(midnight.getTime() / 1000);
+
+    try {
+        const result = await query(`
+            SELECT MAX(MeterReading) - MIN(MeterReading), MIN(Timestamp), MAX(Timestamp)
+            FROM Readings WHERE Meter = ${quote(meter)} AND Timestamp >= ${from}`);
+
+        const [energy, first, last] = result.rows[0] || [];
+        if (energy === null || energy === undefined) {
+            $("today-energy").textContent = "–";
+            $("today-detail").textContent = "No readings today.";
+            return;
+        }
+
+        const kilowattHours = energyUnit === "WattHour" ? energy / 1000 : energy;
+        $("today-energy").textContent = kilowattHours.toFixed(2);
+
+        const hours = (last - first) / 3600;
+        const average = hours > 0 ? Math.round(kilowattHours * 1000 / hours) : null;
+        $("today-detail").textContent = average === null
+            ? "Since midnight."
+            : `Since ${new Date(first * 1000).toLocaleTimeString([], { hour: "2-digit", minute: "2-digit" })}, ${average} W on average.`;
+    } catch (error) {
+        $("today-detail").textContent = error.message;
+    }
+}
+
+// history chart
+
+const chart = {
+    canvas: $("chart"),
+    range: null,
+    // ranges zoomed out of, for "Zoom out".
+    zooms: [],
+    points: [],
+    bucket: 1,
+    selection: null,
+    request: 0,
+};
+
+function setRange(from, to) {
+    chart.range = { from: Math.floor(from), to: Math.ceil(to) };
+    loadHistory();
+}
+
+async function loadHistory() {
+    const { from, to } = chart.range;
+    const bucket = Math.max(1, Math.ceil((to - from) / CHART_POINTS));
+    const request = ++chart.request;
+
+    $("chart-status").textContent = "Loading…";
+
+    try {
+        const result = await query(`
+            SELECT Timestamp / ${bucket} * ${bucket} AS Time, AVG(LineOne), AVG(LineTwo), AVG(LineThree),
+                AVG(COALESCE(LineOne, 0) + COALESCE(LineTwo, 0) + COALESCE(LineThree, 0))
+            FROM Readings
+            WHERE Meter = ${quote(meter)} AND Timestamp >= ${from} AND Timestamp < ${to}
+            GROUP BY Time ORDER BY Time`);
+
+        // a newer range was requested in the meantime.
+        if (request !== chart.request) {
+            return;
+        }
+
+        chart.points = result.rows;
+        chart.bucket = bucket;
+        $("chart-status").textContent = result.rows.length
+            ? `${bucket === 1 ? "Every reading" : `Averaged over ${formatDuration(bucket)}`}. Drag across the chart to zoom in.`
+            : "No readings in this range.";
+    } catch (error) {
+        if (request !== chart.request) {
+            return;
+        }
+
+        chart.points = [];
+        $("chart-status").textContent = error.message;
+    }
+
+    drawChart();
+}
+
+function formatDuration(seconds) {
+    if (seconds < 60) return `${seconds} s`;
+    if (seconds < 3600) return `${Math.round(seconds / 60)} min`;
+    return `${Math.round(seconds / 360) / 10} h`;
+}
+
+function formatTime(timestamp, span) {
+    const date = new Date(timestamp * 1000);
+    if (span <= 2 * 86400) {
+        return date.toLocaleTimeString([], { hour: "2-digit", minute: "2-digit" });
+    }
+    return date.toLocaleDateString([], { day: "numeric", month: "short" });
+}
+
+function layout() {
+    const width = chart.canvas.clientWidth;
+    const height = chart.canvas.clientHeight;
+    return { width, height, left: 52, right: width - 10, top: 10, bottom: height - 24 };
+}
+
+function drawChart() {
+    const canvas = chart.canvas;
+    const ratio = window.devicePixelRatio || 1;
+    const area = layout();
+
+    canvas.width = area.width * ratio;
+    canvas.height = area.height * ratio;
+
+    const context = canvas.getContext("2d");
+    context.scale(ratio, ratio);
+    context.clearRect(0, 0, area.width, area.height);
+    context.font = "12px system-ui, sans-serif";
+
+    const { from, to } = chart.range;
+    const values = chart.points.flatMap((row) => row.slice(1).filter((value) => value !== null));
+    let min = Math.min(0, ...values);
+    let max = Math.max(100, ...values);
+    const padding = (max - min) * 0.05;
+    min = min < 0 ? min - padding : 0;
+    max += padding;
+
+    const x = (time) => area.left + (time - from) / (to - from) * (area.right - area.left);
+    const y = (value) => area.bottom - (value - min) / (max - min) * (area.bottom - area.top);
+
+    // grid and labels.
+    context.strokeStyle = css("--border");
+    context.fillStyle = css("--muted");
+    context.lineWidth = 1;
+    context.textAlign = "right";
+    context.textBaseline = "middle";
+
+    const step = niceStep((max - min) / 5);
+    for (let value = Math.ceil(min / step) * step; value <= max; value += step) {
+        context.beginPath();
+        context.moveTo(area.left, y(value));
+        context.lineTo(area.right, y(value));
+        context.stroke();
+        context.fillText(`${Math.round(value)} W`, area.left - 6, y(value));
+    }
+
+    context.textAlign = "center";
+    context.textBaseline = "top";
+    const ticks = Math.max(2, Math.floor((area.right - area.left) / 110));
+    for (let index = 0; index <= ticks; index++) {
+        const time = from + (to - from) * index / ticks;
+        context.fillText(formatTime(time, to - from), x(time), area.bottom + 6);
+    }
+
+    // one line per phase and the total, interrupted where readings are missing.
+    const series = [...LINES, { column: 4, color: "--line-total", width: 2 }];
+    for (const { column, color, width } of series) {
+        context.strokeStyle = css(color);
+        context.lineWidth = width || 1.25;
+        context.beginPath();
+
+        let previous = null;
+        for (const row of chart.points) {
+            const value = row[column];
+            if (value === null || value === undefined) {
+                previous = null;
+                continue;
+            }
+
+            const gap = previous !== null && row[0] - previous > chart.bucket * 3;
+            if (previous === null || gap) {
+                context.moveTo(x(row[0]), y(value));
+            } else {
+                context.lineTo(x(row[0]), y(value));
+            }
+            previous = row[0];
+        }
+
+        context.stroke();
+    }
+
+    if (chart.selection) {
+        const [start, end] = [chart.selection.start, chart.selection.end].sort((a, b) => a - b);
+        context.fillStyle = css("--accent");
+        context.globalAlpha = 0.15;
+        context.fillRect(start, area.top, end - start, area.bottom - area.top);
+        context.globalAlpha = 1;
+    }
+}
+
+function niceStep(rough) {
+    const power = Math.pow(10, Math.floor(Math.log10(rough)));
+    const fraction = rough / power;
+    return (fraction <= 1 ? 1 : fraction <= 2 ? 2 : fraction <= 5 ? 5 : 10) * power;
+}
+
+function timeAt(offsetX) {
+    const area = layout();
+    const { from, to } = chart.range;
+    const share = Math.min(Math.max((offsetX - area.left) / (area.right - area.left), 0), 1);
+    return from + share * (to - from);
+}
+
+function showTooltip(offsetX) {
+    const tooltip = $("tooltip");
+    if (!chart.points.length) {
+        tooltip.hidden = true;
+        return;
+    }
+
+    const time = timeAt(offsetX);
+    let nearest = chart.points[0];
+    for (const row of chart.points) {
+        if (Math.abs(row[0] - time) < Math.abs(nearest[0] - time)) {
+            nearest = row;
+        }
+    }
+
+    const format = (value) => value === null ? "–" : `${Math.round(value)} W`;
+    tooltip.innerHTML = `${new Date(nearest[0] * 1000).toLocaleString()}<br>`
+        + LINES.map((line) => `${line.label} ${format(nearest[line.column])}`).join(" · ")
+        + `<br><b>Total ${format(nearest[4])}</b>`;
+    tooltip.hidden = false;
+
+    const left = Math.min(offsetX + 12, chart.canvas.clientWidth - tooltip.offsetWidth);
+    tooltip.style.left = `${Math.max(0, left)}px`;
+}
+
+function setupChart() {
+    const canvas = chart.canvas;
+
+    canvas.addEventListener("pointerdown", (event) => {
+        chart.selection = { start: event.offsetX, end: event.offsetX };
+        canvas.setPointerCapture(event.pointerId);
+    });
+
+    canvas.addEventListener("pointermove", (event) => {
+        if (chart.selection) {
+            chart.selection.end = event.offsetX;
+            drawChart();
+        }
+        showTooltip(event.offsetX);
+    });
+
+    canvas.addEventListener("pointerup", () => {
+        const selection = chart.selection;
+        chart.selection = null;
+
+        // a tap just shows the tooltip.
+        if (!selection || Math.abs(selection.end - selection.start) < 8) {
+            drawChart();
+            return;
+        }
+
+        const [from, to] = [timeAt(selection.start), timeAt(selection.end)].sort((a, b) => a - b);
+        if (to - from >= 60) {
+            chart.zooms.push(chart.range);
+            setRange(from, to);
+        } else {
+            drawChart();
+        }
+    });
+
+    canvas.addEventListener("pointerleave", () => {
+        if (!chart.selection) {
+            $("tooltip").hidden = true;
+        }
+    });
+
+    // zooms around the pointer, loading the new range once the wheel stops.
+    let wheelTimer = null;
+    canvas.addEventListener("wheel", (event) => {
+        event.preventDefault();
+
+        const { from, to } = chart.range;
+        const center = timeAt(event.offsetX);
+        const factor = event.deltaY > 0 ? 1.25 : 0.8;
+        const newFrom = center - (center - from) * factor;
+        const newTo = center + (to - center) * factor;
+
+        if (newTo - newFrom < 60) {
+            return;
+        }
+
+        chart.range = { from: Math.floor(newFrom), to: Math.ceil(newTo) };
+        drawChart();
+
+        clearTimeout(wheelTimer);
+        wheelTimer = setTimeout(loadHistory, 250);
+    }, { passive: false });
+
+    for (const button of document.querySelectorAll("[data-range]")) {
+        button.addEventListener("click", () => {
+            document.querySelectorAll("[data-range]").forEach((other) => other.classList.remove("selected"));
+            button.classList.add("selected");
+            chart.zooms = [];
+            setRange(now() - Number(button.dataset.range), now());
+        });
+    }
+
+    $("zoom-out").addEventListener("click", () => {
+        const previous = chart.zooms.pop();
+        if (previous) {
+            setRange(previous.from, previous.to);
+        } else {
+            const { from, to } = chart.range;
+            const span = to - from;
+            setRange(from - span / 2, Math.min(to + span / 2, now()));
+        }
+    });
+
+    window.addEventListener("resize", drawChart);
+
+    setRange(now() - 86400, now());
+}
+
+// SQL console
+
+async function runQuery() {
+    const status = $("sql-status");
+    const table = $("result");
+
+    status.className = "muted";
+    status.textContent = "Running…";
+
+    try {
+        const result = await query($("sql").value);
+        status.textContent = `${result.rows_count} rows in ${result.took_ms} ms${result.truncated ? ", truncated" : ""}`;
+
+        table.replaceChildren();
+        const head = table.createTHead().insertRow();
+        for (const column of result.columns) {
+            const cell = document.createElement("th");
+            cell.textContent = column;
+            head.append(cell);
+        }
+
+        const body = table.createTBody();
+        for (const row of result.rows) {
+            const tableRow = body.insertRow();
+            for (const value of row) {
+                tableRow.insertCell().textContent = value === null ? "NULL" : value;
+            }
+        }
+    } catch (error) {
+        status.className = "muted error";
+        status.textContent = error.message;
+    }
+}
+
+function setupConsole() {
+    $("run").addEventListener("click", runQuery);
+    $("sql").addEventListener("keydown", (event) => {
+        if (event.key === "Enter" && (event.ctrlKey || event.metaKey)) {
+            event.preventDefault();
+            runQuery();
+        }
+    });
+}
+
+createGauges();
+setupChart();
+setupConsole();
+pollReadings();
+updateToday();
+setInterval(updateToday, 60 * 1000);
+setInterval(showAge, 1000);
@@ -0,0 +1,113 @@
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use axum::http::header;
+use axum::response::Response;
+
+use crate::core_loop::ReaderMetrics;
+use crate::latest_reading::LatestReading;
+use crate::storage::ReadingStore;
+use crate::unit::Unit;
+
+/// Renders the latest reading and the health of the reader in the Prometheus text format.
+///
+/// Metrics of the reading carry the unit the meter reports as suffix, e.g. `power_meter_energy_watt_hours_total`.
+pub async fn handler(latest_reading: Arc<LatestReading>, metrics: Arc<ReaderMetrics>, store: Arc<dyn ReadingStore + Send + Sync>) -> Response {
+    let size = tokio::task::spawn_blocking(move || store.size()).await.unwrap();
+    let latest = latest_reading.get();
+
+    let mut body = String::new();
+
+    if let Some(latest) = latest.as_ref().map(|latest| &latest.reading) {
+        let reading = &latest.reading;
+        let meter = escape(&latest.meter);
+
+        if let Some(value) = reading.meter_reading {
+            let name = metric_name("power_meter_energy", &reading.meter_reading_unit) + "_total";
+            add(&mut body, &name, "counter", "The energy counter of the meter.", &[(format!("meter=\"{meter}\""), value)]);
+        }
+
+        let lines = [(1, reading.line_one, &reading.line_one_unit), (2, reading.line_two, &reading.line_two_unit), (3, reading.line_three, &reading.line_three_unit)];
+        let mut values = lines.iter()
+            .filter_map(|(line, value, unit)| Some((line, (*value)?, *unit)))
+            .peekable();
+
+        if let Some((_, _, unit)) = values.peek() {
+            let name = metric_name("power_meter_power", unit);
+            let samples = values.map(|(line, value, _)| (format!("meter=\"{meter}\",line=\"{line}\""), value as f64)).collect::<Vec<_>>();
+            add(&mut body, &name, "gauge", "The power per line, negative when feeding in.", &samples);
+        }
+
+        if let Some(total_power) = reading.total_power() {
+            let unit = lines.iter().find_map(|(_, _, unit)| unit.as_ref());
+            let name = metric_name("power_meter_total_power", &unit.cloned());
+            add(&mut body, &name, "gauge", "The power of all lines together.", &[(format!("meter=\"{meter}\""), total_power as f64)]);
+        }
+
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as i64);
+        let samples = [(format!("meter=\"{meter}\""), (now - latest.timestamp) as f64)];
+        add(&mut body, "power_meter_reading_age_seconds", "gauge", "Seconds since the latest reading.", &samples);
+
+        let samples = [(format!("meter=\"{meter}\""), latest.timestamp as f64)];
+        add(&mut body, "power_meter_last_reading_timestamp_seconds", "gauge", "Unix time of the latest reading.", &samples);
+    }
+
+    let counters = [
+        ("power_meter_frames_total", "SML files decoded from the serial port.", &metrics.frames),
+        ("power_meter_transport_errors_total", "Bytes rejected by the SML transport decoder.", &metrics.transport_errors),
+        ("power_meter_parse_errors_total", "Decoded files which didn't contain a valid reading.", &metrics.parse_errors),
+        ("power_meter_readings_total", "Readings stored.", &metrics.readings),
+        ("power_meter_duplicate_readings_total", "Readings dropped for having the timestamp of a stored one.", &metrics.duplicates),
+        ("power_meter_locked_readings_total", "Readings dropped because the storage was locked by another process.", &metrics.locked),
+    ];
+
+    for (name, help, counter) in counters {
+        add(&mut body, name, "counter", help, &[(String::new(), counter.load(Ordering::Relaxed) as f64)]);
+    }
+
+    match size {
+        Ok(Some(size)) => add(&mut body, "power_meter_storage_size_bytes", "gauge", "Space taken by the stored readings.", &[(String::new(), size as f64)]),
+        Ok(None) => {}
+        Err(error) => println!("Warning: Could not determine the storage size: {error}"),
+    }
+
+    Response::builder()
+        .status(200)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(body.into())
+        .unwrap()
+}
+
+fn add(body: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    writeln!(body, "# HELP {name} {help}").unwrap();
+    writeln!(body, "# TYPE {name} {kind}").unwrap();
+
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            writeln!(body, "{name} {value}").unwrap();
+        } else {
+            writeln!(body, "{name}{{{labels}}} {value}").unwrap();
+        }
+    }
+}
+
+fn metric_name(name: &str, unit: &Option<Unit>) -> String {
+    let suffix = match unit {
+        Some(Unit::Watt) => "_watts",
+        Some(Unit::WattHour) => "_watt_hours",
+        Some(Unit::Volt) => "_volts",
+        Some(Unit::Ampere) => "_amperes",
+        Some(Unit::Degree) => "_degrees",
+        Some(Unit::Hertz) => "_hertz",
+        None => "",
+    };
+
+    format!("{name}{suffix}")
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -1,14 +1,31 @@
 mod api;
 mod root;
 mod now;
+mod metrics;
+mod ws;
 
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use axum::extract::{Query, WebSocketUpgrade};
 use axum::Router;
 use axum::routing::{get, post};
-use crossbeam_utils::atomic::AtomicCell;
+use tokio::sync::broadcast;
+use crate::config::Config;
+use crate::core_loop::ReaderMetrics;
 use crate::database::ReadonlyDatabase;
-use crate::meter_reading::MeterReading;
+use crate::demand::LiveDemand;
+use crate::latest_reading::{LatestReading, RecentReadings};
+use crate::storage::{ReadingStore, StoredReading};
+
+/// Handles to what `start` is reading right now.
+pub struct Live {
+    pub latest_reading: Arc<LatestReading>,
+    pub recent_readings: Arc<RecentReadings>,
+    /// Subscribe to receive every new reading.
+    pub readings: broadcast::Sender<StoredReading>,
+    pub demand: Arc<Mutex<LiveDemand>>,
+    pub reader_metrics: Arc<ReaderMetrics>,
+}
 
 pub struct Server {
     app: Router,
@@ -16,20 +33,92 @@ pub struct Server {
 }
 
 impl Server {
-    pub fn create(port: u16, latest_reading_cell: Arc<AtomicCell<Option<MeterReading>>>) -> Self {
-        let latest_reading_cell = (
-            latest_reading_cell.clone(),
-            latest_reading_cell.clone()
-        );
-        
-        let readonly_database = Arc::new(ReadonlyDatabase::load().unwrap());
-        
+    pub fn create(port: u16, live: Live, store: Arc<dyn ReadingStore + Send + Sync>, config: Arc<Config>) -> Self {
+        let Live { latest_reading, recent_readings, readings: live_readings, demand: live_demand, reader_metrics } = live;
+
+        // raw SQL queries and spot prices are only available from the database, whichever store the readings go to.
+        let readonly_database = match ReadonlyDatabase::load() {
+            Ok(readonly_database) => Some(Arc::new(readonly_database)),
+            Err(error) => {
+                println!("Warning: /api/query and /api/prices are unavailable: {error}");
+                None
+            }
+        };
+
         // build our application with a single route
-        let app = Router::new()
+        let mut app = Router::new()
             .route("/", get(root::get_handler))
-            .route("/now", get(move || now::handler(latest_reading_cell.0.clone())))
-            .route("/api/now", get(move || api::now::handler(latest_reading_cell.1.clone())))
-            .route("/api/query", post(move |body: String| api::query::handler(readonly_database.clone(), body)));
+            .route("/dashboard.js", get(root::script_handler))
+            .route("/dashboard.css", get(root::style_handler))
+            .route("/help", get(root::help_handler))
+            .route("/now", get({
+                let latest_reading = latest_reading.clone();
+                move |headers, Query(params)| now::handler(latest_reading.clone(), headers, params)
+            }))
+            .route("/api/now", get({
+                let latest_reading = latest_reading.clone();
+                move |headers, Query(params)| api::now::handler(latest_reading.clone(), headers, params)
+            }))
+            .route("/api/recent", get(move |Query(params)| api::recent::handler(recent_readings.clone(), params)))
+            .route("/api/stream", get({
+                let live_readings = live_readings.clone();
+                move |Query(params)| api::stream::handler(live_readings.clone(), params)
+            }))
+            .route("/ws", get({
+                let store = store.clone();
+                move |upgrade: WebSocketUpgrade, Query(params)| ws::handler(upgrade, store.clone(), live_readings.clone(), params)
+            }))
+            .route("/metrics", get({
+                let store = store.clone();
+                move || metrics::handler(latest_reading.clone(), reader_metrics.clone(), store.clone())
+            }))
+            .route("/api/readings", get({
+                let store = store.clone();
+                move |Query(params)| api::readings::handler(store.clone(), params)
+            }))
+            .route("/api/export", get({
+                let store = store.clone();
+                move |Query(params)| api::export::handler(store.clone(), params)
+            }))
+            .route("/api/consumption", get({
+                let store = store.clone();
+                move |Query(params)| api::consumption::handler(store.clone(), params)
+            }))
+            .route("/api/stats", get({
+                let store = store.clone();
+                move |Query(params)| api::stats::handler(store.clone(), params)
+            }))
+            .route("/api/imbalance", get({
+                let store = store.clone();
+                move |Query(params)| api::imbalance::handler(store.clone(), params)
+            }))
+            .route("/api/base-load", get({
+                let store = store.clone();
+                move |Query(params)| api::base_load::handler(store.clone(), params)
+            }))
+            .route("/api/demand", get({
+                let store = store.clone();
+                let config = config.clone();
+                move |Query(params)| api::demand::handler(store.clone(), config.clone(), params)
+            }))
+            .route("/api/demand/now", get(move || api::demand::now_handler(live_demand.clone())))
+            .route("/api/cost", get({
+                let readonly_database = readonly_database.clone();
+                move |Query(params)| api::cost::handler(store.clone(), readonly_database.clone(), config.clone(), params)
+            }));
+
+        if let Some(readonly_database) = readonly_database {
+            let readonly_database = (
+                readonly_database.clone(),
+                readonly_database.clone(),
+                readonly_database.clone()
+            );
+
+            app = app
+                .route("/api/query", post(move |body: String| api::query::handler(readonly_database.0.clone(), body)))
+                .route("/api/prices", get(move |Query(params)| api::prices::handler(readonly_database.1.clone(), params)))
+                .route("/api/prices/cheapest", get(move |Query(params)| api::prices::cheapest_handler(readonly_database.2.clone(), params)));
+        }
 
         Server {
             app,
@@ -39,7 +128,7 @@ impl Server {
 
     pub fn enter(self) -> io::Result<()> {
         tokio::runtime::Builder::new_current_thread()
-            .enable_io()
+            .enable_all()
             .build()
             .unwrap()
             .block_on(async move {
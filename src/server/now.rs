@@ -1,24 +1,11 @@
 use std::sync::Arc;
 
-use axum::http::header;
+use axum::http::HeaderMap;
 use axum::response::Response;
-use crossbeam_utils::atomic::AtomicCell;
 
-use crate::meter_reading::MeterReading;
+use crate::latest_reading::LatestReading;
+use crate::server::api::now::{respond, NowParams};
 
-pub async fn handler(latest_reading_cell: Arc<AtomicCell<Option<MeterReading>>>) -> Response {
-    let reading = latest_reading_cell.take();
-
-    let status = if reading.is_some() { 200 } else { 204 };
-
-    let body = match reading {
-        Some(reading) => format!("{reading}"),
-        None => "".to_string()
-    };
-
-    Response::builder()
-        .status(status)
-        .header(header::CONTENT_TYPE, "text/plain")
-        .body(body.into())
-        .unwrap()
-}
\ No newline at end of file
+pub async fn handler(latest_reading: Arc<LatestReading>, headers: HeaderMap, params: NowParams) -> Response {
+    respond(&latest_reading, &headers, params, "text/plain", |latest| format!("{}", latest.reading)).await
+}
@@ -1,13 +1,56 @@
 use axum::http::header;
 use axum::response::Response;
 
+// the dashboard is compiled into the binary, so it works without any files next to it.
+const INDEX_HTML: &str = include_str!("dashboard/index.html");
+const DASHBOARD_JS: &str = include_str!("dashboard/dashboard.js");
+const DASHBOARD_CSS: &str = include_str!("dashboard/dashboard.css");
+
+/// Serves the dashboard, which shows the live power per line, today's consumption, the history and a SQL console.
 pub async fn get_handler() -> Response {
+    asset("text/html; charset=utf-8", INDEX_HTML)
+}
+
+pub async fn script_handler() -> Response {
+    asset("text/javascript; charset=utf-8", DASHBOARD_JS)
+}
+
+pub async fn style_handler() -> Response {
+    asset("text/css; charset=utf-8", DASHBOARD_CSS)
+}
+
+fn asset(content_type: &str, content: &'static str) -> Response {
+    Response::builder()
+        .status(200)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(content.into())
+        .unwrap()
+}
+
+pub async fn help_handler() -> Response {
     let help_text = "
         Service is running.
 
+        GET / - dashboard
+        GET /help - this list
         GET /now - get the latest meter reading
-        GET /api/now - get the latest meter reading as JSON
+        GET /api/now - get the latest meter reading as JSON, ?after=<sequence> waits for a newer one
+        GET /api/recent - every reading of the last ?seconds=300 from memory
+        GET /api/stream - every new reading as Server-Sent Events
+        GET /ws - WebSocket with subscriptions to meters and fields
+        GET /metrics - the latest reading and the health of the reader for Prometheus
         POST /api/query - query the database with readonly SQLite statements
+        GET /api/readings?from=&to=&fields=&limit=&cursor= - stored readings page by page
+        GET /api/export?from=&to=&format=csv|jsonl|parquet&rollup=minute|hour|day - export readings
+        GET /api/consumption?from=&to=&interval=hour|day|month - energy used per interval
+        GET /api/stats?from=&to=&bucket=hour|day|month - min, max, mean, median and p95 of the power
+        GET /api/imbalance?from=&to=&bucket=hour|day|month&threshold= - how evenly the power is spread over the lines
+        GET /api/base-load?from=&to=&period=day|week - always-on consumption and its trend
+        GET /api/demand?from=&to=&window=&sliding= - highest demand per month
+        GET /api/demand/now - projected demand of the current window
+        GET /api/cost?from=&to=&period=day|month|billing - energy cost with the configured tariff
+        GET /api/prices?from=&to= - imported spot prices
+        GET /api/prices/cheapest?hours=&within=&consecutive= - cheapest upcoming hours
     ";
     
     Response::builder()
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, Error};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::meter_reading::MeterReading;
+use crate::storage::{ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::TimeRange;
+
+/// Maximum number of seconds of history sent at once, so clients reconnecting after a long outage don't load the whole database.
+const MAX_HISTORY: i64 = 3600;
+
+/// The fields a client can subscribe to, by name or by OBIS code.
+const FIELDS: [(&str, Option<&str>); 6] = [
+    ("meter_reading", Some("1-0:1.8.0")),
+    ("line_one", Some("1-0:36.7.0")),
+    ("line_two", Some("1-0:56.7.0")),
+    ("line_three", Some("1-0:76.7.0")),
+    ("total_power", Some("1-0:16.7.0")),
+    ("feed_in", None),
+];
+
+#[derive(Deserialize)]
+pub struct WsParams {
+    /// Comma separated, all meters if missing.
+    meters: Option<String>,
+    /// Comma separated names or OBIS codes, all fields if missing.
+    fields: Option<String>,
+    interval: Option<f64>,
+    history: Option<i64>,
+    since: Option<i64>,
+}
+
+/// A message from the client.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// Replaces the current subscription.
+    Subscribe {
+        #[serde(default)]
+        meters: Vec<String>,
+        #[serde(default)]
+        fields: Vec<String>,
+        interval: Option<f64>,
+        history: Option<i64>,
+        since: Option<i64>,
+    },
+    /// Stops the updates until the next subscription.
+    Unsubscribe,
+}
+
+/// Which readings a client receives and how often.
+struct Subscription {
+    /// Empty for all meters.
+    meters: Vec<String>,
+    /// Names of the fields, empty for all.
+    fields: Vec<&'static str>,
+    /// Minimum time between two updates of a meter, readings in between are skipped.
+    interval: Duration,
+    /// When the latest update of each meter was sent.
+    sent: HashMap<String, Instant>,
+}
+
+impl Subscription {
+    fn new(meters: Vec<String>, fields: &[String], interval: Option<f64>) -> Result<Self, Error> {
+        let fields = fields.iter()
+            .map(|field| {
+                FIELDS.iter()
+                    .find(|(name, obis_code)| name == field || *obis_code == Some(field.as_str()))
+                    .map(|(name, _)| *name)
+                    .ok_or_else(|| anyhow!("Unknown field \"{field}\"."))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let interval = interval.unwrap_or_default();
+        if !(0.0..=86400.0).contains(&interval) {
+            bail!("The interval has to be between 0 and 86400 seconds.");
+        }
+
+        Ok(Subscription {
+            meters,
+            fields,
+            interval: Duration::from_secs_f64(interval),
+            sent: HashMap::new(),
+        })
+    }
+
+    fn includes(&self, meter: &str) -> bool {
+        self.meters.is_empty() || self.meters.iter().any(|subscribed| subscribed == meter)
+    }
+
+    /// Returns the update for a live reading, or `None` if it isn't subscribed or comes too early.
+    fn update(&mut self, reading: &StoredReading) -> Option<Value> {
+        if !self.includes(&reading.meter) {
+            return None;
+        }
+
+        let now = Instant::now();
+        if self.sent.get(&reading.meter).is_some_and(|sent| now.duration_since(*sent) < self.interval) {
+            return None;
+        }
+
+        self.sent.insert(reading.meter.clone(), now);
+        let mut update = self.encode(reading);
+        update["type"] = json!("reading");
+        Some(update)
+    }
+
+    fn encode(&self, reading: &StoredReading) -> Value {
+        let values = values(&reading.reading).into_iter()
+            .filter(|(name, _)| self.fields.is_empty() || self.fields.contains(name))
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .collect::<Map<_, _>>();
+
+        json!({
+            "timestamp": reading.timestamp,
+            "meter": reading.meter,
+            "values": values,
+        })
+    }
+}
+
+/// Upgrades to a WebSocket sending the subscribed readings as JSON.
+///
+/// The query parameters set the subscription on connect, `{"type": "subscribe", ...}` messages replace it later on.
+/// `history` sends the readings of the last seconds and `since` those after a timestamp, e.g. the latest one
+/// received before a reconnect, as one `history` message before any live `reading`.
+pub async fn handler(upgrade: WebSocketUpgrade, store: Arc<dyn ReadingStore + Send + Sync>, live_readings: broadcast::Sender<StoredReading>, params: WsParams) -> Response {
+    // subscribed before the history is loaded, so no reading falls in between.
+    let readings = live_readings.subscribe();
+
+    upgrade.on_upgrade(move |socket| async move {
+        let split = |list: Option<String>| list.map_or(Vec::new(), |list| list.split(',').map(str::to_string).collect());
+        let request = Request::Subscribe {
+            meters: split(params.meters),
+            fields: split(params.fields),
+            interval: params.interval,
+            history: params.history,
+            since: params.since,
+        };
+
+        connection(socket, store, readings, request).await;
+    })
+}
+
+async fn connection(mut socket: WebSocket, store: Arc<dyn ReadingStore + Send + Sync>, mut readings: broadcast::Receiver<StoredReading>, request: Request) {
+    let mut subscription = None;
+    // readings up to this timestamp were sent as history.
+    let mut sent_until = i64::MIN;
+
+    let mut request = Some(request);
+
+    loop {
+        if let Some(request) = request.take() {
+            let messages = match subscribe(&store, request).await {
+                Ok((new_subscription, history)) => {
+                    let mut messages = Vec::new();
+
+                    if let Some(new_subscription) = &new_subscription {
+                        sent_until = history.last().map_or(i64::MIN, |reading| reading.timestamp);
+                        if !history.is_empty() {
+                            let history = history.iter().map(|reading| new_subscription.encode(reading)).collect::<Vec<_>>();
+                            messages.push(json!({ "type": "history", "readings": history }));
+                        }
+                    }
+
+                    subscription = new_subscription;
+                    messages
+                }
+                Err(error) => vec![json!({ "type": "error", "error": error.to_string() })],
+            };
+
+            for message in messages {
+                if socket.send(Message::Text(message.to_string())).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(parsed) => request = Some(parsed),
+                    Err(error) => {
+                        let message = json!({ "type": "error", "error": format!("Invalid request: {error}") });
+                        if socket.send(Message::Text(message.to_string())).await.is_err() {
+                            return;
+                        }
+                    }
+                },
+                // pings are answered by axum.
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_))) => {}
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+            },
+            reading = readings.recv() => {
+                let reading = match reading {
+                    Ok(reading) => reading,
+                    // a client too slow to keep up misses some readings instead of holding up the others.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                };
+
+                if reading.timestamp <= sent_until {
+                    continue;
+                }
+
+                let Some(update) = subscription.as_mut().and_then(|subscription| subscription.update(&reading)) else {
+                    continue;
+                };
+
+                if socket.send(Message::Text(update.to_string())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the new subscription, `None` after unsubscribing, and the history it asked for.
+async fn subscribe(store: &Arc<dyn ReadingStore + Send + Sync>, request: Request) -> Result<(Option<Subscription>, Vec<StoredReading>), Error> {
+    let Request::Subscribe { meters, fields, interval, history, since } = request else {
+        return Ok((None, Vec::new()));
+    };
+
+    let subscription = Subscription::new(meters, &fields, interval)?;
+
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
+    let Some(from) = history_start(history, since, now)? else {
+        return Ok((Some(subscription), Vec::new()));
+    };
+
+    let meters = subscription.meters.clone();
+    let store = store.clone();
+    let history = tokio::task::spawn_blocking(move || -> Result<Vec<StoredReading>, Error> {
+        let mut query = ReadingQuery::new(TimeRange::new(Some(from), None)?);
+        if let [meter] = meters.as_slice() {
+            query.meter = Some(meter.clone());
+        }
+
+        store.readings(&query)?
+            .filter(|reading| reading.as_ref().map_or(true, |reading| meters.is_empty() || meters.contains(&reading.meter)))
+            .collect()
+    }).await.unwrap()?;
+
+    Ok((Some(subscription), history))
+}
+
+/// Returns the first timestamp of the history asked for, `None` if there is none.
+fn history_start(history: Option<i64>, since: Option<i64>, now: i64) -> Result<Option<i64>, Error> {
+    match (history, since) {
+        (Some(_), Some(_)) => bail!("Use either `history` or `since`."),
+        (Some(history), None) if history < 0 => bail!("The history can't be negative."),
+        (Some(history), None) => Ok(Some(now - history.min(MAX_HISTORY))),
+        (None, Some(since)) => Ok(Some(since.saturating_add(1).max(now - MAX_HISTORY))),
+        (None, None) => Ok(None),
+    }
+}
+
+fn values(reading: &MeterReading) -> [(&'static str, Option<Value>); 6] {
+    let total_power = reading.total_power();
+
+    [
+        ("meter_reading", reading.meter_reading.map(Value::from)),
+        ("line_one", reading.line_one.map(Value::from)),
+        ("line_two", reading.line_two.map(Value::from)),
+        ("line_three", reading.line_three.map(Value::from)),
+        ("total_power", total_power.map(Value::from)),
+        ("feed_in", total_power.map(|power| Value::from((-power).max(0)))),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+
+    fn subscription(fields: &[&str], interval: Option<f64>) -> Result<Subscription, Error> {
+        Subscription::new(Vec::new(), &fields.iter().map(|field| field.to_string()).collect::<Vec<_>>(), interval)
+    }
+
+    fn reading(timestamp: i64, meter: &str) -> StoredReading {
+        StoredReading {
+            timestamp,
+            meter: meter.to_string(),
+            reading: MeterReading { meter_reading: Some(1234.5), line_one: Some(-100), ..Default::default() },
+        }
+    }
+
+    #[test]
+    fn resolves_fields_by_name_and_obis_code() {
+        let subscription = subscription(&["meter_reading", "1-0:36.7.0", "feed_in"], None).unwrap();
+        assert_eq!(subscription.fields, ["meter_reading", "line_one", "feed_in"]);
+
+        let encoded = subscription.encode(&reading(100, "default"));
+        assert_eq!(encoded, json!({ "timestamp": 100, "meter": "default", "values": { "meter_reading": 1234.5, "line_one": -100, "feed_in": 100 } }));
+
+        assert_eq!(self::subscription(&["1-0:99.9.9"], None).err().unwrap().to_string(), "Unknown field \"1-0:99.9.9\".");
+    }
+
+    #[test]
+    fn limits_the_interval() {
+        assert!(subscription(&[], Some(0.0)).is_ok());
+        assert!(subscription(&[], Some(86400.0)).is_ok());
+
+        for interval in [-1.0, 86400.5, f64::NAN, f64::INFINITY] {
+            assert!(subscription(&[], Some(interval)).is_err(), "{interval}");
+        }
+    }
+
+    #[test]
+    fn skips_updates_within_the_interval_per_meter() {
+        let mut throttled = Subscription::new(vec!["grid".to_string(), "solar".to_string()], &[], Some(3600.0)).unwrap();
+
+        assert_eq!(throttled.update(&reading(100, "grid")).unwrap()["type"], "reading");
+        assert!(throttled.update(&reading(101, "grid")).is_none());
+        assert!(throttled.update(&reading(101, "solar")).is_some());
+        assert!(throttled.update(&reading(102, "heat_pump")).is_none());
+
+        let mut every_reading = subscription(&[], None).unwrap();
+        assert!(every_reading.update(&reading(100, "grid")).is_some());
+        assert!(every_reading.update(&reading(101, "grid")).is_some());
+    }
+
+    #[test]
+    fn starts_the_history_within_the_last_hour() {
+        let now = 1_000_000;
+
+        assert_eq!(history_start(None, None, now).unwrap(), None);
+        assert_eq!(history_start(Some(60), None, now).unwrap(), Some(now - 60));
+        assert_eq!(history_start(Some(i64::MAX), None, now).unwrap(), Some(now - MAX_HISTORY));
+        assert_eq!(history_start(None, Some(now - 60), now).unwrap(), Some(now - 59));
+        assert_eq!(history_start(None, Some(0), now).unwrap(), Some(now - MAX_HISTORY));
+        assert_eq!(history_start(None, Some(i64::MAX), now).unwrap(), Some(i64::MAX));
+
+        assert!(history_start(Some(-1), None, now).is_err());
+        assert!(history_start(Some(60), Some(now), now).is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_the_history_of_the_subscribed_meters() {
+        let store = MemoryStore::default();
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as i64;
+        for (timestamp, meter) in [(now - 120, "grid"), (now - 30, "grid"), (now - 30, "solar"), (now - 20, "heat_pump")] {
+            let StoredReading { reading, .. } = reading(timestamp, meter);
+            store.insert(meter, timestamp, &reading).unwrap();
+        }
+
+        let store: Arc<dyn ReadingStore + Send + Sync> = Arc::new(store);
+        let request = Request::Subscribe {
+            meters: vec!["grid".to_string(), "solar".to_string()],
+            fields: Vec::new(),
+            interval: None,
+            history: Some(60),
+            since: None,
+        };
+
+        let (subscription, history) = subscribe(&store, request).await.unwrap();
+
+        assert!(subscription.is_some());
+        let history = history.iter().map(|reading| (reading.timestamp, reading.meter.as_str())).collect::<Vec<_>>();
+        assert_eq!(history, [(now - 30, "grid"), (now - 30, "solar")]);
+
+        let (subscription, history) = subscribe(&store, Request::Unsubscribe).await.unwrap();
+        assert!(subscription.is_none() && history.is_empty());
+    }
+}
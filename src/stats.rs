@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use serde::Serialize;
+
+use crate::storage::{ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::{Interval, TimeRange};
+use crate::unit::Unit;
+
+/// Statistics of the power values within a bucket.
+#[derive(Serialize)]
+pub struct Summary {
+    pub count: u64,
+    pub min: i64,
+    pub max: i64,
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+}
+
+#[derive(Serialize)]
+pub struct PowerStats {
+    pub from: i64,
+    pub to: i64,
+    pub line_one: Option<Summary>,
+    pub line_two: Option<Summary>,
+    pub line_three: Option<Summary>,
+    /// The sum of all lines, see `MeterReading::total_power`.
+    pub total: Option<Summary>,
+}
+
+#[derive(Serialize)]
+pub struct StatsReport {
+    pub meter: String,
+    pub bucket: Option<Interval>,
+    pub unit: Option<Unit>,
+    pub buckets: Vec<PowerStats>,
+}
+
+/// The values of a bucket counted so far, per line and total.
+#[derive(Default)]
+struct Values([Distribution; 4]);
+
+impl Values {
+    fn into_stats(self, from: i64, to: i64) -> PowerStats {
+        let [line_one, line_two, line_three, total] = self.0.map(|distribution| distribution.summarize());
+
+        PowerStats { from, to, line_one, line_two, line_three, total }
+    }
+}
+
+/// How often each value occurred.
+///
+/// The power is in whole watts and only takes a limited range of values, so this stays small
+/// however many readings there are, while the percentiles are still exact.
+#[derive(Default)]
+pub struct Distribution {
+    counts: BTreeMap<i64, u64>,
+    count: u64,
+    sum: i128,
+}
+
+impl Distribution {
+    pub fn add(&mut self, value: i64) {
+        *self.counts.entry(value).or_default() += 1;
+        self.count += 1;
+        self.sum += i128::from(value);
+    }
+
+    fn summarize(self) -> Option<Summary> {
+        let (min, max) = (*self.counts.first_key_value()?.0, *self.counts.last_key_value()?.0);
+
+        Some(Summary {
+            count: self.count,
+            min,
+            max,
+            mean: self.sum as f64 / self.count as f64,
+            median: self.percentile(0.5),
+            p95: self.percentile(0.95),
+        })
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the percentile `p` (0 to 1), interpolating between the two closest values.
+    /// There has to be at least one value.
+    pub fn percentile(&self, p: f64) -> f64 {
+        let rank = p * (self.count - 1) as f64;
+        let lower = self.nth(rank.floor() as u64) as f64;
+        let upper = self.nth(rank.ceil() as u64) as f64;
+
+        lower + (upper - lower) * rank.fract()
+    }
+
+    /// Returns the value at `index` of the sorted values.
+    fn nth(&self, index: u64) -> i64 {
+        let mut skipped = 0;
+        for (value, count) in &self.counts {
+            skipped += count;
+            if index < skipped {
+                return *value;
+            }
+        }
+
+        unreachable!("index {index} out of {} values", self.count)
+    }
+}
+
+/// Computes statistics of the power per line and in total, per `bucket` or over the whole range.
+/// Without a bucket, `from` and `to` of the result span the readings found.
+///
+/// Percentiles interpolate linearly between the two closest values, so the median of an even
+/// number of values is the mean of the middle two.
+pub fn power_stats(store: &dyn ReadingStore, range: &TimeRange, meter: &str, bucket: Option<Interval>) -> Result<StatsReport, Error> {
+    let query = ReadingQuery {
+        meter: Some(meter.to_string()),
+        ..ReadingQuery::new(*range)
+    };
+
+    let mut unit = None;
+    let mut buckets = Vec::new();
+    let mut current: Option<(i64, i64, Values)> = None;
+    let mut last_timestamp = 0;
+
+    for reading in store.readings(&query)? {
+        let StoredReading { timestamp, reading, .. } = reading?;
+
+        if current.as_ref().is_some_and(|(_, end, _)| timestamp >= *end) {
+            let (start, end, values) = current.take().unwrap();
+            buckets.push(values.into_stats(start.max(range.from), end.min(range.to)));
+        }
+
+        let (_, _, values) = match &mut current {
+            Some(current) => current,
+            None => {
+                let (start, end) = match bucket {
+                    Some(bucket) => {
+                        let start = bucket.start_of(timestamp)?;
+                        (start, bucket.next(start)?)
+                    }
+                    // the whole range, which is narrowed down to the readings below.
+                    None => (timestamp, i64::MAX),
+                };
+
+                current.insert((start, end, Values::default()))
+            }
+        };
+
+        if bucket.is_none() {
+            last_timestamp = timestamp;
+        }
+
+        let lines = [reading.line_one, reading.line_two, reading.line_three];
+        for (values, value) in values.0.iter_mut().zip(lines) {
+            if let Some(value) = value {
+                values.add(value as i64);
+            }
+        }
+        if let Some(total_power) = reading.total_power() {
+            values.0[3].add(total_power);
+        }
+
+        unit = unit.or(reading.line_one_unit).or(reading.line_two_unit).or(reading.line_three_unit);
+    }
+
+    if let Some((start, end, values)) = current {
+        let end = if bucket.is_some() { end.min(range.to) } else { last_timestamp + 1 };
+        buckets.push(values.into_stats(start.max(range.from), end));
+    }
+
+    Ok(StatsReport {
+        meter: meter.to_string(),
+        bucket,
+        unit,
+        buckets,
+    })
+}
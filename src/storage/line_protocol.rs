@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::meter_reading::MeterReading;
+use crate::storage::{aggregate_readings, Aggregate, Order, ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::TimeRange;
+use crate::unit::Unit;
+
+const MEASUREMENT: &str = "readings";
+
+/// Appends the readings to a text file in InfluxDB line protocol, one line per reading.
+///
+/// The timestamps are unix seconds, so the file can be loaded into InfluxDB with `precision=s`.
+/// Queries scan the whole file, which is fine for the append-mostly workload of a recorder
+/// but gets slow for years of data.
+pub struct LineProtocolStore {
+    path: PathBuf,
+    /// The file opened for appending and the timestamp of the last line written to it per meter.
+    file: Mutex<(File, HashMap<String, i64>)>,
+}
+
+impl LineProtocolStore {
+    /// Opens the file at `path`, by default `readings.lp` in the local data directory.
+    pub fn open(path: Option<PathBuf>) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let Some(mut path) = dirs::data_local_dir() else {
+                    bail!("Could not find the local data directory.");
+                };
+
+                path.push("rusty-power-meter");
+                fs::create_dir_all(&path)?;
+                path.push("readings.lp");
+                path
+            }
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        let mut last_timestamps = HashMap::new();
+        for line in BufReader::new(File::open(&path)?).lines() {
+            if let Ok(reading) = decode_line(&line?) {
+                let last_timestamp = last_timestamps.entry(reading.meter).or_insert(reading.timestamp);
+                *last_timestamp = (*last_timestamp).max(reading.timestamp);
+            }
+        }
+
+        Ok(LineProtocolStore {
+            path,
+            file: Mutex::new((file, last_timestamps)),
+        })
+    }
+
+    /// Reads the file from the start. Lines which can't be decoded are skipped with a warning, like `open` does.
+    fn scan(&self) -> Result<impl Iterator<Item = Result<StoredReading, Error>> + '_, Error> {
+        let lines = BufReader::new(File::open(&self.path)?).lines().enumerate();
+
+        Ok(lines.filter_map(|(index, line)| match line {
+            Ok(line) if line.trim().is_empty() || line.starts_with('#') => None,
+            Ok(line) => match decode_line(&line) {
+                Ok(reading) => Some(Ok(reading)),
+                Err(error) => {
+                    println!("Warning: Skipped {}:{}: {error}", self.path.display(), index + 1);
+                    None
+                }
+            },
+            Err(error) => Some(Err(error.into())),
+        }))
+    }
+}
+
+impl ReadingStore for LineProtocolStore {
+    fn insert(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, Error> {
+        let mut file = self.file.lock().unwrap();
+        let (file, last_timestamps) = &mut *file;
+
+        // the file is only ever appended to, so it has to stay in order. Meters may share a timestamp.
+        let latest = last_timestamps.values().max();
+        let duplicate = last_timestamps.get(meter).is_some_and(|last_timestamp| timestamp <= *last_timestamp);
+        if duplicate || latest.is_some_and(|latest| timestamp < *latest) {
+            return Ok(false);
+        }
+
+        let Some(line) = encode_line(meter, timestamp, reading) else {
+            return Ok(false);
+        };
+
+        file.write_all(line.as_bytes())?;
+        last_timestamps.insert(meter.to_string(), timestamp);
+
+        Ok(true)
+    }
+
+    fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error> {
+        let order = query.order;
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+        let query = query.clone();
+        let readings = self.scan()?.filter(move |reading| match reading {
+            Ok(reading) => query.matches(&reading.meter, reading.timestamp),
+            Err(_) => true,
+        });
+
+        match order {
+            Order::Asc => Ok(Box::new(readings.take(limit))),
+            Order::Desc => {
+                let mut readings = readings.collect::<Result<Vec<_>, _>>()?;
+                readings.reverse();
+                readings.truncate(limit);
+
+                Ok(Box::new(readings.into_iter().map(Ok)))
+            }
+        }
+    }
+
+    fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        let query = ReadingQuery {
+            meter: meter.map(str::to_string),
+            ..ReadingQuery::new(*range)
+        };
+
+        aggregate_readings(self.readings(&query)?, bucket_secs)
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        Ok(Some(fs::metadata(&self.path)?.len()))
+    }
+}
+
+/// Encodes a reading as a line, e.g.
+/// `readings,meter=default,meter_reading_unit=Wh meter_time=1234i,meter_reading=5678.9 1709251200`.
+///
+/// Returns `None` if the reading has no values, as a line needs at least one field.
+pub fn encode_line(meter: &str, timestamp: i64, reading: &MeterReading) -> Option<String> {
+    let mut tags = format!("{MEASUREMENT},meter={}", escape(meter));
+    let mut fields = Vec::new();
+
+    let mut add = |name: &str, value: Option<String>, unit: &Option<Unit>| {
+        if let Some(value) = value {
+            if let Some(unit) = unit {
+                tags.push_str(&format!(",{name}_unit={}", escape(unit.as_str())));
+            }
+
+            fields.push(format!("{name}={value}"));
+        }
+    };
+
+    add("meter_time", reading.meter_time.map(|value| format!("{value}i")), &None);
+    add("meter_reading", reading.meter_reading.map(|value| format!("{value:?}")), &reading.meter_reading_unit);
+    add("line_one", reading.line_one.map(|value| format!("{value}i")), &reading.line_one_unit);
+    add("line_two", reading.line_two.map(|value| format!("{value}i")), &reading.line_two_unit);
+    add("line_three", reading.line_three.map(|value| format!("{value}i")), &reading.line_three_unit);
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    Some(format!("{tags} {} {timestamp}\n", fields.join(",")))
+}
+
+fn decode_line(line: &str) -> Result<StoredReading, Error> {
+    let [series, fields, timestamp] = split_unescaped(line, ' ')[..] else {
+        bail!("Expected a series, fields and a timestamp.");
+    };
+
+    let mut series = split_unescaped(series, ',').into_iter();
+    if series.next() != Some(MEASUREMENT) {
+        bail!("Unknown measurement.");
+    }
+
+    let mut meter = None;
+    let mut reading = MeterReading::default();
+    let mut units = [None, None, None, None];
+
+    for tag in series {
+        let [key, value] = split_unescaped(tag, '=')[..] else {
+            bail!("Invalid tag \"{tag}\".");
+        };
+
+        let value = unescape(value);
+        match key {
+            "meter" => meter = Some(value),
+            "meter_reading_unit" => units[0] = Unit::from_symbol(&value),
+            "line_one_unit" => units[1] = Unit::from_symbol(&value),
+            "line_two_unit" => units[2] = Unit::from_symbol(&value),
+            "line_three_unit" => units[3] = Unit::from_symbol(&value),
+            _ => {}
+        }
+    }
+
+    for field in split_unescaped(fields, ',') {
+        let [key, value] = split_unescaped(field, '=')[..] else {
+            bail!("Invalid field \"{field}\".");
+        };
+
+        let integer = || value.strip_suffix('i')
+            .ok_or_else(|| anyhow!("Field \"{key}\" is not an integer."))?
+            .parse::<i64>()
+            .map_err(Error::from);
+
+        match key {
+            "meter_time" => reading.meter_time = Some(integer()? as u32),
+            "meter_reading" => reading.meter_reading = Some(value.parse()?),
+            "line_one" => reading.line_one = Some(integer()? as i32),
+            "line_two" => reading.line_two = Some(integer()? as i32),
+            "line_three" => reading.line_three = Some(integer()? as i32),
+            _ => {}
+        }
+    }
+
+    // like the database, only values get a unit.
+    let [meter_reading_unit, line_one_unit, line_two_unit, line_three_unit] = units;
+    reading.meter_reading_unit = reading.meter_reading.and(meter_reading_unit);
+    reading.line_one_unit = reading.line_one.and(line_one_unit);
+    reading.line_two_unit = reading.line_two.and(line_two_unit);
+    reading.line_three_unit = reading.line_three.and(line_three_unit);
+
+    Ok(StoredReading {
+        timestamp: timestamp.parse()?,
+        meter: meter.ok_or_else(|| anyhow!("Missing meter tag."))?,
+        reading,
+    })
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        if matches!(char, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+
+        escaped.push(char);
+    }
+
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => unescaped.extend(chars.next()),
+            char => unescaped.push(char),
+        }
+    }
+
+    unescaped
+}
+
+/// Splits at every `separator` which isn't escaped by a backslash.
+fn split_unescaped(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (index, char) in value.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            char if char == separator => {
+                parts.push(&value[start..index]);
+                start = index + char.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&value[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str, contents: &str) -> LineProtocolStore {
+        let path = std::env::temp_dir().join(format!("line-protocol-{}-{name}.lp", std::process::id()));
+        fs::write(&path, contents).unwrap();
+
+        LineProtocolStore::open(Some(path)).unwrap()
+    }
+
+    fn reading(meter_reading: f64) -> MeterReading {
+        MeterReading { meter_reading: Some(meter_reading), meter_reading_unit: Some(Unit::WattHour), ..Default::default() }
+    }
+
+    #[test]
+    fn encodes_and_decodes_readings() {
+        let reading = MeterReading {
+            meter_time: Some(1234),
+            meter_reading: Some(5678.9),
+            meter_reading_unit: Some(Unit::WattHour),
+            line_one: Some(-42),
+            line_one_unit: Some(Unit::Watt),
+            ..Default::default()
+        };
+
+        let line = encode_line("garage, left", 1_709_251_200, &reading).unwrap();
+        assert_eq!(
+            line,
+            "readings,meter=garage\\,\\ left,meter_reading_unit=Wh,line_one_unit=W meter_time=1234i,meter_reading=5678.9,line_one=-42i 1709251200\n"
+        );
+
+        let decoded = decode_line(line.trim_end()).unwrap();
+        assert_eq!(decoded.meter, "garage, left");
+        assert_eq!(decoded.timestamp, 1_709_251_200);
+        assert_eq!(decoded.reading.units(), reading.units());
+        assert_eq!(decoded.reading.line_one, Some(-42));
+    }
+
+    #[test]
+    fn skips_lines_which_cannot_be_decoded() {
+        let store = open(
+            "skip",
+            "readings,meter=grid meter_reading=1.0 100\nreadings,meter=grid meter_reading=oops 200\nnot a reading\nreadings,meter=grid meter_reading=3.0 300\n",
+        );
+
+        let query = ReadingQuery { order: Order::Desc, ..ReadingQuery::new(TimeRange { from: 0, to: 1000 }) };
+        let readings = store.readings(&query).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let timestamps = readings.iter().map(|reading| reading.timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps, [300, 100]);
+
+        let aggregates = store.aggregates(&TimeRange { from: 0, to: 1000 }, None, 1000).unwrap();
+        assert_eq!(aggregates[0].count, 2);
+
+        fs::remove_file(&store.path).unwrap();
+    }
+
+    #[test]
+    fn appends_in_order_per_meter() {
+        let store = open("order", "readings,meter=grid meter_reading=1.0 100\n");
+
+        assert!(!store.insert("grid", 100, &reading(2.0)).unwrap());
+        assert!(store.insert("solar", 100, &reading(2.0)).unwrap());
+        assert!(store.insert("grid", 200, &reading(3.0)).unwrap());
+        // the file would be out of order.
+        assert!(!store.insert("solar", 150, &reading(4.0)).unwrap());
+
+        let readings = store.readings(&ReadingQuery::new(TimeRange { from: 0, to: 1000 })).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let readings = readings.iter().map(|reading| (reading.timestamp, reading.meter.as_str(), reading.reading.meter_reading)).collect::<Vec<_>>();
+        assert_eq!(readings, [(100, "grid", Some(1.0)), (100, "solar", Some(2.0)), (200, "grid", Some(3.0))]);
+
+        fs::remove_file(&store.path).unwrap();
+    }
+}
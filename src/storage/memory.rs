@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use anyhow::Error;
+
+use crate::meter_reading::MeterReading;
+use crate::storage::{aggregate_readings, Aggregate, Order, ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::TimeRange;
+
+/// Keeps all readings in memory, ordered by timestamp and meter. Mostly useful for tests and trying things out.
+#[derive(Default)]
+pub struct MemoryStore {
+    readings: RwLock<BTreeMap<(i64, String), StoredReading>>,
+}
+
+impl ReadingStore for MemoryStore {
+    fn insert(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, Error> {
+        let mut readings = self.readings.write().unwrap();
+        let key = (timestamp, meter.to_string());
+        if readings.contains_key(&key) {
+            return Ok(false);
+        }
+
+        readings.insert(key, StoredReading {
+            timestamp,
+            meter: meter.to_string(),
+            reading: reading.clone(),
+        });
+
+        Ok(true)
+    }
+
+    fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error> {
+        let readings = self.readings.read().unwrap();
+        let range = readings.range((query.range.from, String::new())..(query.range.to, String::new())).map(|(_, reading)| reading);
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+
+        let selected = match query.order {
+            Order::Asc => range.filter(|reading| query.matches(&reading.meter, reading.timestamp)).take(limit).cloned().collect::<Vec<_>>(),
+            Order::Desc => range.rev().filter(|reading| query.matches(&reading.meter, reading.timestamp)).take(limit).cloned().collect(),
+        };
+
+        Ok(Box::new(selected.into_iter().map(Ok)))
+    }
+
+    fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        let query = ReadingQuery {
+            meter: meter.map(str::to_string),
+            ..ReadingQuery::new(*range)
+        };
+
+        aggregate_readings(self.readings(&query)?, bucket_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(meter_reading: f64, line_one: i32) -> MeterReading {
+        MeterReading { meter_reading: Some(meter_reading), line_one: Some(line_one), ..Default::default() }
+    }
+
+    fn selected(store: &MemoryStore, query: &ReadingQuery) -> Vec<(i64, String)> {
+        store.readings(query).unwrap().map(|reading| {
+            let reading = reading.unwrap();
+            (reading.timestamp, reading.meter)
+        }).collect()
+    }
+
+    #[test]
+    fn rejects_duplicate_timestamps_per_meter() {
+        let store = MemoryStore::default();
+
+        assert!(store.insert("grid", 100, &reading(1.0, 1)).unwrap());
+        assert!(store.insert("solar", 100, &reading(1.0, 1)).unwrap());
+        assert!(!store.insert("grid", 100, &reading(2.0, 2)).unwrap());
+
+        let readings = store.readings(&ReadingQuery::new(TimeRange { from: 0, to: 200 })).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0].reading.meter_reading, Some(1.0));
+    }
+
+    #[test]
+    fn selects_by_range_meter_order_and_limit() {
+        let store = MemoryStore::default();
+        for timestamp in [100, 200, 300, 400] {
+            store.insert("grid", timestamp, &reading(1.0, 1)).unwrap();
+            store.insert("solar", timestamp, &reading(1.0, 1)).unwrap();
+        }
+
+        let range = TimeRange { from: 200, to: 400 };
+        assert_eq!(
+            selected(&store, &ReadingQuery::new(range)),
+            [(200, "grid".into()), (200, "solar".into()), (300, "grid".into()), (300, "solar".into())]
+        );
+
+        let query = ReadingQuery { meter: Some("solar".into()), limit: Some(1), order: Order::Desc, ..ReadingQuery::new(range) };
+        assert_eq!(selected(&store, &query), [(300, "solar".into())]);
+    }
+
+    #[test]
+    fn aggregates_into_buckets() {
+        let store = MemoryStore::default();
+        store.insert("grid", 0, &reading(10.0, 100)).unwrap();
+        store.insert("grid", 30, &reading(12.0, 200)).unwrap();
+        store.insert("grid", 90, &reading(15.0, 400)).unwrap();
+        store.insert("solar", 30, &reading(99.0, 999)).unwrap();
+
+        let aggregates = store.aggregates(&TimeRange { from: 0, to: 120 }, Some("grid"), 60).unwrap();
+
+        let aggregates = aggregates.iter().map(|aggregate| (aggregate.timestamp, aggregate.count, aggregate.meter_reading, aggregate.line_one)).collect::<Vec<_>>();
+        assert_eq!(aggregates, [(0, 2, Some(12.0), Some(150.0)), (60, 1, Some(15.0), Some(400.0))]);
+    }
+}
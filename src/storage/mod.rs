@@ -0,0 +1,264 @@
+mod line_protocol;
+mod memory;
+mod sqlite;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Error;
+use clap_derive::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Alert;
+use crate::database::{Database, ReadonlyDatabase};
+use crate::meter_reading::MeterReading;
+use crate::timestamp::TimeRange;
+
+pub use line_protocol::{encode_line, LineProtocolStore};
+pub use memory::MemoryStore;
+
+/// Where readings are stored.
+///
+/// `CoreLoop` writes into a store and the HTTP server reads from it, so alternative
+/// storage only requires another implementation of this trait.
+pub trait ReadingStore {
+    /// Stores a reading taken at `timestamp` (unix seconds).
+    ///
+    /// Returns `false` without storing anything if the meter already has a reading at this timestamp.
+    fn insert(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, Error>;
+
+    /// Records the units a meter reports, for stores which don't keep them with every reading.
+    fn register_units(&self, _meter: &str, _reading: &MeterReading) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Logs a notification of the alert rules, for stores which keep them.
+    fn insert_alert(&self, _alert: &Alert, _delivered: Option<bool>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error>;
+
+    /// Rolls the readings within `range` up into buckets of `bucket_secs` seconds, aligned to UTC.
+    fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error>;
+
+    /// The space taken by the stored readings in bytes, `None` if it isn't known.
+    fn size(&self) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+}
+
+impl<T: ReadingStore + ?Sized> ReadingStore for Arc<T> {
+    fn insert(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, Error> {
+        (**self).insert(meter, timestamp, reading)
+    }
+
+    fn register_units(&self, meter: &str, reading: &MeterReading) -> Result<(), Error> {
+        (**self).register_units(meter, reading)
+    }
+
+    fn insert_alert(&self, alert: &Alert, delivered: Option<bool>) -> Result<(), Error> {
+        (**self).insert_alert(alert, delivered)
+    }
+
+    fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error> {
+        (**self).readings(query)
+    }
+
+    fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        (**self).aggregates(range, meter, bucket_secs)
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        (**self).size()
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Selects readings by time range and meter.
+#[derive(Clone)]
+pub struct ReadingQuery {
+    pub range: TimeRange,
+    pub meter: Option<String>,
+    pub limit: Option<u64>,
+    pub order: Order,
+}
+
+impl ReadingQuery {
+    pub fn new(range: TimeRange) -> Self {
+        ReadingQuery {
+            range,
+            meter: None,
+            limit: None,
+            order: Order::Asc,
+        }
+    }
+
+    fn matches(&self, meter: &str, timestamp: i64) -> bool {
+        timestamp >= self.range.from
+            && timestamp < self.range.to
+            && self.meter.as_deref().is_none_or(|name| name == meter)
+    }
+}
+
+/// A stored reading together with the time it was taken and the meter it was taken from.
+#[derive(Clone, Serialize)]
+pub struct StoredReading {
+    pub timestamp: i64,
+    pub meter: String,
+    #[serde(flatten)]
+    pub reading: MeterReading,
+}
+
+/// The readings of one bucket of time.
+///
+/// `meter_reading` is the last counter value within the bucket and the lines hold the average power.
+#[derive(Serialize)]
+pub struct Aggregate {
+    pub timestamp: i64,
+    pub count: u64,
+    pub meter_reading: Option<f64>,
+    pub line_one: Option<f64>,
+    pub line_two: Option<f64>,
+    pub line_three: Option<f64>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StorageKind {
+    /// SQLite database in the local data directory
+    Sqlite,
+    /// Append-only file in InfluxDB line protocol
+    LineProtocol,
+    /// Kept in memory only, lost on exit
+    Memory,
+}
+
+/// A store for `CoreLoop` to write into and one for other threads to read from.
+///
+/// These are the same store unless the backend uses separate connections for reading.
+pub struct Storage {
+    pub writer: Box<dyn ReadingStore>,
+    pub reader: Arc<dyn ReadingStore + Send + Sync>,
+    kind: StorageKind,
+}
+
+impl Storage {
+    /// Opens another writer into the same store for a thread other than `CoreLoop`'s, e.g. to log alerts.
+    pub fn background_writer(&self) -> Result<Box<dyn ReadingStore + Send>, Error> {
+        match self.kind {
+            // a connection of its own, as connections can't be shared between threads.
+            StorageKind::Sqlite => Ok(Box::new(Database::load()?)),
+            StorageKind::LineProtocol | StorageKind::Memory => Ok(Box::new(self.reader.clone())),
+        }
+    }
+}
+
+/// Selects the storage backend, shared by all subcommands which access readings.
+#[derive(Clone, Args)]
+pub struct StorageArgs {
+    /// Where readings are stored.
+    #[arg(long, value_enum, default_value = "sqlite")]
+    storage: StorageKind,
+
+    /// File of the line protocol store. Defaults to `readings.lp` in the local data directory.
+    #[arg(long)]
+    storage_path: Option<PathBuf>,
+}
+
+impl StorageArgs {
+    pub fn open(self) -> Result<Storage, Error> {
+        match self.storage {
+            StorageKind::Sqlite => Ok(Storage {
+                writer: Box::new(Database::load()?),
+                reader: Arc::new(ReadonlyDatabase::load()?),
+                kind: self.storage,
+            }),
+            StorageKind::LineProtocol => {
+                let store = Arc::new(LineProtocolStore::open(self.storage_path)?);
+                Ok(Storage { writer: Box::new(store.clone()), reader: store, kind: self.storage })
+            }
+            StorageKind::Memory => {
+                let store = Arc::new(MemoryStore::default());
+                Ok(Storage { writer: Box::new(store.clone()), reader: store, kind: self.storage })
+            }
+        }
+    }
+
+    /// Opens the store for reading only, which for SQLite skips opening a writable connection.
+    pub fn open_reader(self) -> Result<Arc<dyn ReadingStore + Send + Sync>, Error> {
+        match self.storage {
+            StorageKind::Sqlite => Ok(Arc::new(ReadonlyDatabase::load()?)),
+            _ => Ok(self.open()?.reader),
+        }
+    }
+}
+
+/// Buckets readings one by one, for stores which can't aggregate on their own.
+fn aggregate_readings(
+    readings: impl Iterator<Item = Result<StoredReading, Error>>,
+    bucket_secs: i64,
+) -> Result<Vec<Aggregate>, Error> {
+    /// Sums up the values of a line within the current bucket.
+    #[derive(Default)]
+    struct Average(f64, u64);
+
+    impl Average {
+        fn add(&mut self, value: Option<i32>) {
+            if let Some(value) = value {
+                self.0 += value as f64;
+                self.1 += 1;
+            }
+        }
+
+        fn get(&self) -> Option<f64> {
+            (self.1 > 0).then(|| self.0 / self.1 as f64)
+        }
+    }
+
+    if bucket_secs <= 0 {
+        anyhow::bail!("Invalid bucket size {bucket_secs}.");
+    }
+
+    let mut aggregates = Vec::<Aggregate>::new();
+    let mut averages = [Average::default(), Average::default(), Average::default()];
+
+    for reading in readings {
+        let StoredReading { timestamp, reading, .. } = reading?;
+        let bucket = timestamp.div_euclid(bucket_secs) * bucket_secs;
+
+        if aggregates.last().is_none_or(|aggregate| aggregate.timestamp != bucket) {
+            averages = Default::default();
+            aggregates.push(Aggregate {
+                timestamp: bucket,
+                count: 0,
+                meter_reading: None,
+                line_one: None,
+                line_two: None,
+                line_three: None,
+            });
+        }
+
+        let aggregate = aggregates.last_mut().unwrap();
+        aggregate.count += 1;
+        aggregate.meter_reading = match (aggregate.meter_reading, reading.meter_reading) {
+            (Some(current), Some(value)) => Some(current.max(value)),
+            (current, value) => current.or(value),
+        };
+
+        averages[0].add(reading.line_one);
+        averages[1].add(reading.line_two);
+        averages[2].add(reading.line_three);
+        aggregate.line_one = averages[0].get();
+        aggregate.line_two = averages[1].get();
+        aggregate.line_three = averages[2].get();
+    }
+
+    Ok(aggregates)
+}
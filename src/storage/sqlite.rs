@@ -0,0 +1,51 @@
+use anyhow::{bail, Error};
+
+use crate::alerts::Alert;
+use crate::database::{Database, ReadonlyDatabase};
+use crate::meter_reading::MeterReading;
+use crate::storage::{Aggregate, ReadingQuery, ReadingStore, StoredReading};
+use crate::timestamp::TimeRange;
+
+impl ReadingStore for Database {
+    fn insert(&self, meter: &str, timestamp: i64, reading: &MeterReading) -> Result<bool, Error> {
+        self.insert_reading_at(meter, timestamp, reading)
+    }
+
+    fn register_units(&self, meter: &str, reading: &MeterReading) -> Result<(), Error> {
+        self.update_meter_units(meter, reading)
+    }
+
+    fn insert_alert(&self, alert: &Alert, delivered: Option<bool>) -> Result<(), Error> {
+        Database::insert_alert(self, alert, delivered)
+    }
+
+    fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error> {
+        Ok(Box::new(Database::readings(self, query)?))
+    }
+
+    fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        Database::aggregates(self, range, meter, bucket_secs)
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        Database::size(self).map(Some)
+    }
+}
+
+impl ReadingStore for ReadonlyDatabase {
+    fn insert(&self, _meter: &str, _timestamp: i64, _reading: &MeterReading) -> Result<bool, Error> {
+        bail!("The database is opened readonly.")
+    }
+
+    fn readings<'a>(&'a self, query: &ReadingQuery) -> Result<Box<dyn Iterator<Item = Result<StoredReading, Error>> + 'a>, Error> {
+        Ok(Box::new(ReadonlyDatabase::readings(self, query)?))
+    }
+
+    fn aggregates(&self, range: &TimeRange, meter: Option<&str>, bucket_secs: i64) -> Result<Vec<Aggregate>, Error> {
+        ReadonlyDatabase::aggregates(self, range, meter, bucket_secs)
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        ReadonlyDatabase::size(self).map(Some)
+    }
+}
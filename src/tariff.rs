@@ -0,0 +1,353 @@
+use anyhow::{anyhow, bail, Error};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+
+/// Prices of a time-of-use tariff, the `[tariff]` section of the config.
+///
+/// ```toml
+/// [tariff]
+/// currency = "EUR"
+/// holidays = ["2024-12-25", "2024-12-26"]
+/// billing_start = "2024-03-15"
+///
+/// [[tariff.periods]]
+/// monthly_fee = 12.5
+/// feed_in = 0.082
+/// windows = [
+///     { days = ["weekday"], from = "06:00", to = "22:00", price = 0.35 },
+///     { price = 0.25 },
+/// ]
+///
+/// [[tariff.periods]]
+/// from = "2025-01-01"
+/// monthly_fee = 14.0
+/// windows = [{ price = 0.31 }]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tariff {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+
+    /// Days which are billed like a Sunday.
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+
+    /// First day of a billing period. Further periods follow every `billing_months`.
+    pub billing_start: Option<NaiveDate>,
+
+    #[serde(default = "default_billing_months")]
+    pub billing_months: u32,
+
+    /// The prices over time, ordered by `from`. Each period is valid until the next one starts.
+    pub periods: Vec<TariffPeriod>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TariffPeriod {
+    /// First day of this period. Only the first period may leave it out.
+    pub from: Option<NaiveDate>,
+
+    #[serde(default)]
+    pub monthly_fee: f64,
+
+    /// Compensation per kWh fed into the grid.
+    #[serde(default)]
+    pub feed_in: f64,
+
+    /// Prices per kWh, the first matching window applies.
+    pub windows: Vec<PriceWindow>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceWindow {
+    /// Days this window applies to, all days if empty.
+    #[serde(default)]
+    pub days: Vec<Days>,
+
+    /// Local time the window starts, midnight if left out.
+    pub from: Option<NaiveTime>,
+
+    /// Local time the window ends (exclusive), midnight if left out. May be before `from` to span midnight.
+    pub to: Option<NaiveTime>,
+
+    pub price: f64,
+
+    /// Adds the imported spot price to `price`, which is then a markup, for dynamic tariffs.
+    #[serde(default)]
+    pub spot: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Days {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+    Weekday,
+    Weekend,
+    Holiday,
+}
+
+fn default_currency() -> String {
+    "EUR".to_string()
+}
+
+fn default_billing_months() -> u32 {
+    12
+}
+
+impl Tariff {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.periods.is_empty() {
+            bail!("The tariff needs at least one period.");
+        }
+
+        if self.billing_months == 0 {
+            bail!("The billing period needs to be at least one month.");
+        }
+
+        for (index, period) in self.periods.iter().enumerate() {
+            if period.windows.is_empty() {
+                bail!("Tariff period {} has no price windows.", index + 1);
+            }
+
+            if index > 0 {
+                let Some(from) = period.from else {
+                    bail!("Tariff period {} has no start date.", index + 1);
+                };
+
+                if self.periods[index - 1].from.is_some_and(|previous| previous >= from) {
+                    bail!("Tariff periods must be ordered by their start date.");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the period valid on `date`, if the tariff already started.
+    pub fn period_at(&self, date: NaiveDate) -> Option<&TariffPeriod> {
+        self.periods.iter().rev().find(|period| period.from.is_none_or(|from| from <= date))
+    }
+
+    /// A dynamic tariff which is nothing but the spot prices, used when no tariff is configured.
+    pub fn spot_only() -> Self {
+        Tariff {
+            currency: default_currency(),
+            holidays: Vec::new(),
+            billing_start: None,
+            billing_months: default_billing_months(),
+            periods: vec![TariffPeriod {
+                from: None,
+                monthly_fee: 0.0,
+                feed_in: 0.0,
+                windows: vec![PriceWindow { days: Vec::new(), from: None, to: None, price: 0.0, spot: true }],
+            }],
+        }
+    }
+
+    /// Returns the price window applying at the given local time.
+    pub fn window_at(&self, date_time: NaiveDateTime) -> Result<&PriceWindow, Error> {
+        let date = date_time.date();
+        let period = self.period_at(date).ok_or_else(|| anyhow!("The tariff has no price for {date}."))?;
+        let holiday = self.holidays.contains(&date);
+
+        period.windows.iter()
+            .find(|window| window.matches(date_time, holiday))
+            .ok_or_else(|| anyhow!("No price window of the tariff matches {date_time}."))
+    }
+
+    /// Returns the first day of the billing period containing `date` and the first day after it.
+    pub fn billing_period(&self, date: NaiveDate) -> Result<(NaiveDate, NaiveDate), Error> {
+        let Some(billing_start) = self.billing_start else {
+            bail!("The tariff has no billing_start.");
+        };
+
+        // periods are counted from billing_start, so short months don't shift the following periods.
+        let length = self.billing_months as i32;
+        let months = (date.year() - billing_start.year()) * 12 + date.month() as i32 - billing_start.month() as i32;
+        let mut index = months.div_euclid(length) - 1;
+
+        while add_months(billing_start, (index + 1) * length)? <= date {
+            index += 1;
+        }
+
+        Ok((add_months(billing_start, index * length)?, add_months(billing_start, (index + 1) * length)?))
+    }
+}
+
+impl PriceWindow {
+    fn matches(&self, date_time: NaiveDateTime, holiday: bool) -> bool {
+        // holidays count as Sunday.
+        let weekday = if holiday { Weekday::Sun } else { date_time.weekday() };
+        let weekend = matches!(weekday, Weekday::Sat | Weekday::Sun);
+
+        let day_matches = self.days.is_empty() || self.days.iter().any(|days| match days {
+            Days::Weekday => !weekend,
+            Days::Weekend => weekend,
+            Days::Holiday => holiday,
+            day => *day == Days::from(weekday),
+        });
+
+        let time = date_time.time();
+        let from = self.from.unwrap_or(NaiveTime::MIN);
+        let time_matches = match self.to {
+            Some(to) if to > from => from <= time && time < to,
+            Some(to) if to < from => from <= time || time < to,
+            _ => from <= time,
+        };
+
+        day_matches && time_matches
+    }
+}
+
+impl From<Weekday> for Days {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Mon => Days::Mon,
+            Weekday::Tue => Days::Tue,
+            Weekday::Wed => Days::Wed,
+            Weekday::Thu => Days::Thu,
+            Weekday::Fri => Days::Fri,
+            Weekday::Sat => Days::Sat,
+            Weekday::Sun => Days::Sun,
+        }
+    }
+}
+
+fn add_months(date: NaiveDate, months: i32) -> Result<NaiveDate, Error> {
+    let result = if months >= 0 {
+        date.checked_add_months(Months::new(months as u32))
+    } else {
+        date.checked_sub_months(Months::new(months.unsigned_abs()))
+    };
+
+    result.ok_or_else(|| anyhow!("Date out of range."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARIFF: &str = r#"
+        holidays = ["2024-12-25"]
+        billing_start = "2024-03-15"
+
+        [[periods]]
+        monthly_fee = 12.5
+        feed_in = 0.082
+        windows = [
+            { days = ["weekday"], from = "06:00", to = "22:00", price = 0.35 },
+            { days = ["sat"], from = "22:00", to = "06:00", price = 0.15 },
+            { price = 0.25 },
+        ]
+
+        [[periods]]
+        from = "2025-01-01"
+        monthly_fee = 14.0
+        windows = [{ price = 0.31 }]
+    "#;
+
+    fn tariff(toml: &str) -> Tariff {
+        let tariff = toml::from_str::<Tariff>(toml).unwrap();
+        tariff.validate().unwrap();
+
+        tariff
+    }
+
+    fn price_at(tariff: &Tariff, date_time: &str) -> Result<f64, Error> {
+        let date_time = NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M").unwrap();
+        tariff.window_at(date_time).map(|window| window.price)
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn selects_the_first_matching_window() {
+        let tariff = tariff(TARIFF);
+
+        // 2024-12-23 is a Monday.
+        for (date_time, price) in [
+            ("2024-12-23 05:59", 0.25),
+            ("2024-12-23 06:00", 0.35),
+            ("2024-12-23 21:59", 0.35),
+            ("2024-12-23 22:00", 0.25),
+            // a window spanning midnight applies to both ends of its days.
+            ("2024-12-21 03:00", 0.15),
+            ("2024-12-21 12:00", 0.25),
+            ("2024-12-21 23:00", 0.15),
+            ("2024-12-22 03:00", 0.25),
+            ("2024-12-22 12:00", 0.25),
+        ] {
+            assert_eq!(price_at(&tariff, date_time).unwrap(), price, "{date_time}");
+        }
+    }
+
+    #[test]
+    fn bills_holidays_like_a_sunday() {
+        let tariff = tariff(TARIFF);
+
+        assert_eq!(price_at(&tariff, "2024-12-24 12:00").unwrap(), 0.35);
+        assert_eq!(price_at(&tariff, "2024-12-25 12:00").unwrap(), 0.25);
+
+        let tariff = self::tariff(r#"
+            holidays = ["2024-12-25"]
+            periods = [{ windows = [{ days = ["holiday"], price = 0.1 }, { days = ["sun"], price = 0.2 }, { price = 0.3 }] }]
+        "#);
+        assert_eq!(price_at(&tariff, "2024-12-25 12:00").unwrap(), 0.1);
+        assert_eq!(price_at(&tariff, "2024-12-22 12:00").unwrap(), 0.2);
+    }
+
+    #[test]
+    fn changes_the_period_on_its_first_day() {
+        let tariff = tariff(TARIFF);
+
+        assert_eq!(price_at(&tariff, "2024-12-31 23:59").unwrap(), 0.25);
+        assert_eq!(price_at(&tariff, "2025-01-01 00:00").unwrap(), 0.31);
+        assert_eq!(tariff.period_at(date("2024-12-31")).unwrap().monthly_fee, 12.5);
+        assert_eq!(tariff.period_at(date("2025-01-01")).unwrap().monthly_fee, 14.0);
+        assert_eq!(tariff.period_at(date("2030-01-01")).unwrap().monthly_fee, 14.0);
+    }
+
+    #[test]
+    fn has_no_price_before_the_first_period() {
+        let tariff = tariff(r#"periods = [{ from = "2024-01-01", windows = [{ price = 0.3 }] }]"#);
+
+        assert!(price_at(&tariff, "2023-12-31 23:59").is_err());
+        assert_eq!(price_at(&tariff, "2024-01-01 00:00").unwrap(), 0.3);
+    }
+
+    #[test]
+    fn fails_without_a_matching_window() {
+        let tariff = tariff(r#"periods = [{ windows = [{ days = ["weekday"], price = 0.3 }] }]"#);
+
+        assert!(price_at(&tariff, "2024-12-22 12:00").is_err());
+    }
+
+    #[test]
+    fn rejects_unordered_periods() {
+        let tariff = toml::from_str::<Tariff>(r#"
+            periods = [{ windows = [{ price = 0.3 }] }, { from = "2025-01-01", windows = [{ price = 0.3 }] }, { from = "2024-01-01", windows = [{ price = 0.3 }] }]
+        "#).unwrap();
+
+        assert_eq!(tariff.validate().unwrap_err().to_string(), "Tariff periods must be ordered by their start date.");
+    }
+
+    #[test]
+    fn counts_billing_periods_from_their_start() {
+        let tariff = tariff(TARIFF);
+
+        assert_eq!(tariff.billing_period(date("2024-03-15")).unwrap(), (date("2024-03-15"), date("2025-03-15")));
+        assert_eq!(tariff.billing_period(date("2025-03-14")).unwrap(), (date("2024-03-15"), date("2025-03-15")));
+        assert_eq!(tariff.billing_period(date("2024-03-14")).unwrap(), (date("2023-03-15"), date("2024-03-15")));
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// A request received by the stand-in server.
+pub struct Request {
+    /// E.g. `POST /api/v2/write?bucket=meter HTTP/1.1`.
+    pub request_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Starts a local HTTP server standing in for a webhook or InfluxDB, which answers every
+/// request with `status`. Returns its base URL and the requests it receives.
+pub fn serve(status: u16) -> (String, Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => headers.push((name.to_string(), value.trim().to_string())),
+                    None => break,
+                }
+            }
+
+            let request = Request { request_line: request_line.trim_end().to_string(), headers, body: String::new() };
+            let length = request.header("content-length").map_or(0, |length| length.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            write!(stream, "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+
+            if sender.send(Request { body: String::from_utf8(body).unwrap(), ..request }).is_err() {
+                break;
+            }
+        }
+    });
+
+    (url, receiver)
+}
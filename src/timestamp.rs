@@ -0,0 +1,147 @@
+use anyhow::{anyhow, bail, Error};
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use clap_derive::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Parses a point in time into unix seconds, which is how `Readings.Timestamp` is stored.
+///
+/// Accepted are unix seconds (`1709251200`), RFC 3339 (`2024-03-01T00:00:00+01:00`),
+/// a local date time (`2024-03-01T00:00:00`) and a local date (`2024-03-01`, meaning midnight).
+pub fn parse_timestamp(value: &str) -> Result<i64, Error> {
+    if let Ok(seconds) = value.parse::<i64>() {
+        return Ok(seconds);
+    }
+
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.timestamp());
+    }
+
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return local_timestamp(date_time);
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return local_timestamp(date.and_time(Default::default()));
+    }
+
+    bail!("Invalid timestamp \"{value}\". Expected unix seconds, RFC 3339 or YYYY-MM-DD.")
+}
+
+/// Converts a local date time into unix seconds.
+///
+/// Ambiguous local times (e.g. when clocks are turned back) resolve to the earlier instant.
+pub fn local_timestamp(date_time: NaiveDateTime) -> Result<i64, Error> {
+    Local.from_local_datetime(&date_time)
+        .earliest()
+        .map(|date_time| date_time.timestamp())
+        .ok_or_else(|| anyhow!("Local time {date_time} does not exist."))
+}
+
+/// Switches the local time zone of the tests to Central European Time, so they cover daylight saving time
+/// wherever they run. Every test which depends on the local time calls this first.
+#[cfg(test)]
+pub fn use_test_time_zone() {
+    static TIME_ZONE: std::sync::Once = std::sync::Once::new();
+    TIME_ZONE.call_once(|| std::env::set_var("TZ", "CET-1CEST,M3.5.0,M10.5.0/3"));
+}
+
+/// Formats unix seconds as local date time, e.g. `2024-03-01 13:37:00`.
+pub fn format_timestamp(timestamp: i64) -> String {
+    format_local(timestamp, "%Y-%m-%d %H:%M:%S")
+}
+
+/// Formats unix seconds as local time with a `strftime` like format.
+pub fn format_local(timestamp: i64, format: &str) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(date_time) => date_time.with_timezone(&Local).format(format).to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// A half-open range of unix seconds, `from` inclusive and `to` exclusive.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeRange {
+    pub from: i64,
+    pub to: i64,
+}
+
+impl TimeRange {
+    /// Creates a range from optional bounds, leaving missing bounds open.
+    pub fn new(from: Option<i64>, to: Option<i64>) -> Result<Self, Error> {
+        let range = TimeRange {
+            from: from.unwrap_or(0),
+            to: to.unwrap_or(i64::MAX),
+        };
+
+        if range.from > range.to {
+            bail!("Invalid time range: from is after to.");
+        }
+
+        Ok(range)
+    }
+
+    /// Creates a range from optional bounds in any format accepted by `parse_timestamp`.
+    pub fn parse(from: Option<&str>, to: Option<&str>) -> Result<Self, Error> {
+        let from = from.map(parse_timestamp).transpose()?;
+        let to = to.map(parse_timestamp).transpose()?;
+
+        Self::new(from, to)
+    }
+}
+
+/// Calendar intervals in local time, e.g. for consumption per day.
+///
+/// Unlike fixed buckets these follow the local clock, so a day may be 23 or 25 hours long.
+#[derive(Clone, Copy, Debug, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Hour,
+    Day,
+    /// Monday to Sunday
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// Returns the start of the interval containing `timestamp`.
+    pub fn start_of(&self, timestamp: i64) -> Result<i64, Error> {
+        let date_time = DateTime::from_timestamp(timestamp, 0)
+            .ok_or_else(|| anyhow!("Invalid timestamp {timestamp}."))?
+            .with_timezone(&Local);
+
+        match self {
+            Interval::Hour => {
+                // whole hours of the local clock, which may be offset by e.g. 30 minutes from UTC.
+                let offset = date_time.offset().fix().local_minus_utc() as i64;
+                Ok(timestamp - (timestamp + offset).rem_euclid(60 * 60))
+            }
+            Interval::Day => local_timestamp(date_time.date_naive().and_time(Default::default())),
+            Interval::Week => {
+                let date = date_time.date_naive() - Days::new(date_time.weekday().num_days_from_monday() as u64);
+                local_timestamp(date.and_time(Default::default()))
+            }
+            Interval::Month => local_timestamp(date_time.date_naive().with_day(1).unwrap().and_time(Default::default())),
+        }
+    }
+
+    /// Returns the start of the interval following the one starting at `start`.
+    pub fn next(&self, start: i64) -> Result<i64, Error> {
+        match self {
+            Interval::Hour => Ok(start + 60 * 60),
+            Interval::Day | Interval::Week | Interval::Month => {
+                let date = DateTime::from_timestamp(start, 0)
+                    .ok_or_else(|| anyhow!("Invalid timestamp {start}."))?
+                    .with_timezone(&Local)
+                    .date_naive();
+
+                let next = match self {
+                    Interval::Day => date.succ_opt(),
+                    Interval::Week => date.checked_add_days(Days::new(7)),
+                    _ => date.with_day(1).and_then(|date| date.checked_add_months(Months::new(1))),
+                };
+
+                local_timestamp(next.ok_or_else(|| anyhow!("Date out of range."))?.and_time(Default::default()))
+            }
+        }
+    }
+}
@@ -45,6 +45,21 @@ impl Unit {
         }
     }
 
+    /// Creates a `Unit` instance from the string returned by `as_str`.
+    ///
+    /// Returns `None` if the given string doesn't match one of the supported units.
+    pub fn from_symbol(value: &str) -> Option<Self> {
+        match value {
+            "W" => Some(Unit::Watt),
+            "Wh" => Some(Unit::WattHour),
+            "V" => Some(Unit::Volt),
+            "A" => Some(Unit::Ampere),
+            "°" => Some(Unit::Degree),
+            "Hz" => Some(Unit::Hertz),
+            _ => None,
+        }
+    }
+
     /// Creates a `Unit` instance from a DLMN/COSEM unit number.
     ///
     /// Returns `None` if the given unit number doesn't match one of the supported units.